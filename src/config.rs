@@ -1,21 +1,65 @@
+use crate::device::{Bus, DeviceInfo, DeviceMatch};
 use crate::events::*;
+use crate::mode::DEFAULT_MODE;
+use crate::uinput::{KeyCombo, MouseButton};
 #[allow(unused)]
 use log::{debug, error, info, trace, warn};
 use serde::Deserialize;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
+use std::convert::TryFrom;
 use std::ffi::OsStr;
+use std::fmt;
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 const PREFIX: Option<&'static str> = option_env!("PREFIX");
 
-pub(crate) type Device = String;
-pub(crate) type GestureMap = BTreeMap<Gesture, Action>;
+pub(crate) type Mode = String;
+
+/// The `device` value that automatically selects all touchpads
+const AUTO_DEVICE: &str = "auto";
+pub(crate) type GestureMap = BTreeMap<GesturePattern, Action>;
 
 type BoxedError = Box<dyn std::error::Error + Send + Sync>;
 type Result<T> = std::result::Result<T, BoxedError>;
 
+/// The input device(s) a `[[device]]` entry applies to.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Device {
+    /// The path of a device node, e.g. `/dev/input/by-path/...`
+    Path(String),
+    /// All devices with the given properties
+    Match(DeviceMatch),
+}
+
+impl fmt::Display for Device {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Device::Path(path) => write!(f, "{path}"),
+            Device::Match(selector) => write!(f, "devices matching {selector}"),
+        }
+    }
+}
+
 pub(crate) struct Configuration {
-    pub devices: BTreeMap<Device, GestureMap>,
+    pub devices: BTreeMap<Device, DeviceConfig>,
+}
+
+#[derive(Default)]
+pub(crate) struct DeviceConfig {
+    pub settings: Settings,
+    /// The gesture bindings of each mode
+    pub modes: BTreeMap<Mode, GestureMap>,
+}
+
+impl DeviceConfig {
+    /// The gesture bindings in effect in `mode`. Devices without any bindings for the mode keep
+    /// using those of the default mode.
+    pub fn gestures(&self, mode: &str) -> Option<&GestureMap> {
+        self.modes
+            .get(mode)
+            .or_else(|| self.modes.get(DEFAULT_MODE))
+    }
 }
 
 impl Configuration {
@@ -27,13 +71,213 @@ impl Configuration {
 }
 
 #[derive(Deserialize)]
-#[serde(rename_all = "lowercase")]
-#[derive(Default)]
+#[serde(try_from = "ActionSpec")]
+#[derive(Clone, Default)]
 pub(crate) enum Action {
-    #[serde(skip)]
     #[default]
     None,
+    /// Run a command with `sh -c`, e.g. `execute = "notify-send hello"`.
     Execute(String),
+    /// Run a program directly without a shell, e.g. `exec = ["notify-send", "hello"]`.
+    Exec {
+        argv: Vec<String>,
+        cwd: Option<PathBuf>,
+        env: BTreeMap<String, String>,
+    },
+    /// Press a key combination through the virtual uinput keyboard, e.g. `keys = "alt+Left"`.
+    Keys(KeyCombo),
+    /// Click a mouse button through the virtual uinput mouse, e.g. `button = "middle"`.
+    Click(MouseButton),
+    /// Wait before carrying out the next action of a sequence, e.g. `sleep_ms = 150`.
+    Sleep(Duration),
+    /// Carry out several actions in order, e.g. `actions = [{ keys = "super" }, { sleep_ms = 150 }]`.
+    Sequence(Vec<Action>),
+    /// Switch to a different set of gesture bindings, e.g. `set_mode = "presentation"`.
+    SetMode(Mode),
+}
+
+/// Describes the action in terms of the configuration keys it was specified with.
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Action::None => write!(f, "nothing"),
+            Action::Execute(cmd) => write!(f, "execute {cmd:?}"),
+            Action::Exec { argv, .. } => write!(f, "exec {argv:?}"),
+            Action::Keys(keys) => write!(f, "keys {keys}"),
+            Action::Click(button) => write!(f, "button {}", format!("{button:?}").to_lowercase()),
+            Action::Sleep(duration) => write!(f, "sleep_ms {}", duration.as_millis()),
+            Action::Sequence(actions) => {
+                let actions: Vec<String> = actions.iter().map(Action::to_string).collect();
+                write!(f, "actions [{}]", actions.join(", "))
+            }
+            Action::SetMode(mode) => write!(f, "set_mode {mode:?}"),
+        }
+    }
+}
+
+/// The keys of a gesture binding that describe its action, flattened alongside the gesture
+/// itself. Exactly one of the action keys must be present.
+#[derive(Deserialize)]
+struct ActionSpec {
+    execute: Option<String>,
+    exec: Option<Vec<String>>,
+    cwd: Option<PathBuf>,
+    env: Option<BTreeMap<String, String>>,
+    keys: Option<KeyCombo>,
+    button: Option<MouseButton>,
+    sleep_ms: Option<u64>,
+    actions: Option<Vec<Action>>,
+    set_mode: Option<Mode>,
+}
+
+impl TryFrom<ActionSpec> for Action {
+    type Error = String;
+
+    fn try_from(spec: ActionSpec) -> std::result::Result<Self, Self::Error> {
+        let ActionSpec {
+            execute,
+            exec,
+            cwd,
+            env,
+            keys,
+            button,
+            sleep_ms,
+            actions: sequence,
+            set_mode,
+        } = spec;
+
+        if exec.is_none() && (cwd.is_some() || env.is_some()) {
+            return Err("cwd and env may only be used with exec".to_owned());
+        }
+
+        let mut actions = Vec::new();
+        if let Some(cmd) = execute {
+            actions.push(Action::Execute(cmd));
+        }
+        if let Some(argv) = exec {
+            if argv.is_empty() {
+                return Err("exec requires at least the program to run".to_owned());
+            }
+            actions.push(Action::Exec {
+                argv,
+                cwd,
+                env: env.unwrap_or_default(),
+            });
+        }
+        if let Some(keys) = keys {
+            actions.push(Action::Keys(keys));
+        }
+        if let Some(button) = button {
+            actions.push(Action::Click(button));
+        }
+        if let Some(ms) = sleep_ms {
+            actions.push(Action::Sleep(Duration::from_millis(ms)));
+        }
+        if let Some(sequence) = sequence {
+            if sequence.is_empty() {
+                return Err("actions must not be empty".to_owned());
+            }
+            actions.push(Action::Sequence(sequence));
+        }
+        if let Some(mode) = set_mode {
+            actions.push(Action::SetMode(mode));
+        }
+
+        const KEYS: &str = "execute, exec, keys, button, sleep_ms, actions, or set_mode";
+        match actions.len() {
+            0 => Err(format!("No action specified (one of {KEYS})")),
+            1 => Ok(actions.pop().unwrap()),
+            _ => Err(format!("Only one of {KEYS} may be specified")),
+        }
+    }
+}
+
+impl Action {
+    /// Whether this action requires the virtual uinput device to be created.
+    pub fn uses_uinput(&self) -> bool {
+        match self {
+            Action::Keys(_) | Action::Click(_) => true,
+            Action::Sequence(actions) => actions.iter().any(Action::uses_uinput),
+            _ => false,
+        }
+    }
+
+    /// Whether carrying out this action blocks for any length of time.
+    pub fn sleeps(&self) -> bool {
+        match self {
+            Action::Sleep(_) => true,
+            Action::Sequence(actions) => actions.iter().any(Action::sleeps),
+            _ => false,
+        }
+    }
+
+    /// Collects the modes this action switches to.
+    fn mode_targets<'a>(&'a self, targets: &mut BTreeSet<&'a str>) {
+        match self {
+            Action::SetMode(mode) => {
+                targets.insert(mode);
+            }
+            Action::Sequence(actions) => {
+                for action in actions {
+                    action.mode_targets(targets);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Returns the most specific binding matching a gesture performed on a touchpad of the given
+/// `dimensions`, if any.
+pub(crate) fn find_binding<'a>(
+    gestures: &'a GestureMap,
+    event: &GestureEvent,
+    dimensions: Option<&Dimensions>,
+) -> Option<(&'a GesturePattern, &'a Action)> {
+    gestures
+        .iter()
+        .filter(|(pattern, _)| pattern.matches(event, dimensions))
+        .max_by_key(|(pattern, _)| pattern.specificity())
+}
+
+impl Configuration {
+    /// The configuration that applies to the device at `path`, with entries selecting it by path
+    /// taking precedence over those matching its properties.
+    pub fn device_config(&self, path: &str) -> Option<&DeviceConfig> {
+        let canonical_path = std::fs::canonicalize(path).ok()?;
+        let info = DeviceInfo::from_path(path);
+        self.devices
+            .iter()
+            .find(|(device, _)| match device {
+                Device::Path(device_path) => std::fs::canonicalize(device_path)
+                    .is_ok_and(|device_path| device_path == canonical_path),
+                Device::Match(selector) => info.as_ref().is_some_and(|info| selector.matches(info)),
+            })
+            .map(|(_, device_config)| device_config)
+    }
+
+    pub fn uses_uinput(&self) -> bool {
+        self.devices
+            .values()
+            .flat_map(|device| device.modes.values())
+            .any(|gestures| gestures.values().any(Action::uses_uinput))
+    }
+
+    /// Warns about `set_mode` actions switching to modes that no device has any bindings for.
+    fn check_modes(&self) {
+        let gesture_maps = || self.devices.values().flat_map(|device| device.modes.iter());
+        let mut targets = BTreeSet::new();
+        for (_, gestures) in gesture_maps() {
+            for action in gestures.values() {
+                action.mode_targets(&mut targets);
+            }
+        }
+        for target in targets {
+            if target != DEFAULT_MODE && !gesture_maps().any(|(mode, _)| mode == target) {
+                warn!("No gestures are bound in mode {target}");
+            }
+        }
+    }
 }
 
 fn get_prefix() -> PathBuf {
@@ -73,6 +317,7 @@ pub(crate) fn load() -> Configuration {
     try_load_config_dir(&mut config, &global_config_dir);
 
     load_user_config(&mut config);
+    config.check_modes();
 
     if config.devices.is_empty() {
         error!("No configuration found!");
@@ -191,14 +436,31 @@ fn load_config_file(config: &mut Configuration, path: &Path) -> Result<()> {
     #[derive(Deserialize)]
     struct ConfigGestureAndAction {
         #[serde(flatten)]
-        pub gesture: Gesture,
+        pub gesture: GesturePattern,
         #[serde(flatten)]
         pub action: Action,
     }
 
     #[derive(Deserialize)]
     struct ConfigDeviceGestures {
-        pub device: Device,
+        #[serde(default)]
+        pub device: Option<String>,
+        /// A glob pattern matched against the device name
+        #[serde(default)]
+        pub name: Option<String>,
+        #[serde(default)]
+        pub vendor: Option<u16>,
+        #[serde(default)]
+        pub product: Option<u16>,
+        #[serde(default)]
+        pub bus: Option<Bus>,
+        #[serde(default)]
+        pub touchpad: bool,
+        #[serde(default)]
+        pub settings: Option<Settings>,
+        /// The mode the gestures are bound in
+        #[serde(default)]
+        pub mode: Option<Mode>,
         pub gestures: Vec<ConfigGestureAndAction>,
     }
 
@@ -214,13 +476,122 @@ fn load_config_file(config: &mut Configuration, path: &Path) -> Result<()> {
     let config_file: ConfigFile = toml::from_str(toml_str)?;
 
     for device_config in config_file.devices {
-        let device = device_config.device;
+        let mut selector = DeviceMatch {
+            name: device_config.name,
+            vendor: device_config.vendor,
+            product: device_config.product,
+            bus: device_config.bus,
+            touchpad: device_config.touchpad,
+        };
+        let device = match device_config.device {
+            Some(path) if path != AUTO_DEVICE => {
+                if selector != DeviceMatch::default() {
+                    return Err(
+                        "device can't be combined with name, vendor, product, bus, or touchpad"
+                            .into(),
+                    );
+                }
+                Device::Path(path)
+            }
+            Some(_) => {
+                selector.touchpad = true;
+                Device::Match(selector)
+            }
+            // Without any way of picking a device, use every touchpad we can find
+            None if selector == DeviceMatch::default() => Device::Match(DeviceMatch {
+                touchpad: true,
+                ..selector
+            }),
+            None => Device::Match(selector),
+        };
 
-        let device_gestures = config.devices.entry(device).or_default();
+        let device_entry = config.devices.entry(device).or_default();
+        if let Some(settings) = device_config.settings {
+            validate_settings(&settings)?;
+            device_entry.settings = settings;
+        }
+        let mode = device_config.mode.as_deref().unwrap_or(DEFAULT_MODE);
+        let gestures = device_entry.modes.entry(mode.to_owned()).or_default();
         for gesture_action in device_config.gestures {
-            device_gestures.insert(gesture_action.gesture, gesture_action.action);
+            gestures.insert(gesture_action.gesture, gesture_action.action);
         }
     }
 
     Ok(())
 }
+
+fn validate_settings(settings: &Settings) -> Result<()> {
+    if !(0f64..90f64).contains(&settings.diagonal_width) {
+        return Err("diagonal_width must be at least 0 and less than 90 degrees".into());
+    }
+    if settings.direction_bias <= 0f64 {
+        return Err("direction_bias must be greater than zero".into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn actions() {
+        const KEYS: &str = "execute, exec, keys, button, sleep_ms, actions, or set_mode";
+        let none = format!("No action specified (one of {KEYS})");
+        let several = format!("Only one of {KEYS} may be specified");
+        for (input, expected) in [
+            (r#"execute = "echo hi""#, Ok(r#"execute "echo hi""#)),
+            (r#"exec = ["echo", "hi"]"#, Ok(r#"exec ["echo", "hi"]"#)),
+            (
+                r#"exec = ["ls"]
+                cwd = "/tmp"
+                env = { A = "1" }"#,
+                Ok(r#"exec ["ls"]"#),
+            ),
+            (r#"keys = "alt+Left""#, Ok("keys KEY_LEFTALT+KEY_LEFT")),
+            (r#"button = "middle""#, Ok("button middle")),
+            ("sleep_ms = 150", Ok("sleep_ms 150")),
+            (
+                r#"set_mode = "presentation""#,
+                Ok(r#"set_mode "presentation""#),
+            ),
+            (
+                r#"actions = [{ keys = "super" }, { sleep_ms = 150 }]"#,
+                Ok("actions [keys KEY_LEFTMETA, sleep_ms 150]"),
+            ),
+            ("", Err(none.as_str())),
+            (
+                r#"cwd = "/tmp""#,
+                Err("cwd and env may only be used with exec"),
+            ),
+            (
+                r#"execute = "ls"
+                env = { A = "1" }"#,
+                Err("cwd and env may only be used with exec"),
+            ),
+            (
+                "exec = []",
+                Err("exec requires at least the program to run"),
+            ),
+            ("actions = []", Err("actions must not be empty")),
+            (
+                r#"execute = "ls"
+                sleep_ms = 150"#,
+                Err(several.as_str()),
+            ),
+            (
+                r#"keys = "super"
+                button = "left""#,
+                Err(several.as_str()),
+            ),
+        ] {
+            let spec: ActionSpec = toml::from_str(input).unwrap();
+            let action = Action::try_from(spec).map(|action| action.to_string());
+            assert_eq!(
+                action.as_deref().map_err(String::as_str),
+                expected,
+                "{input}"
+            );
+        }
+    }
+}
@@ -0,0 +1,416 @@
+//! Finds input devices by their properties (name, ids, bus, capabilities) rather than by path, so
+//! configurations keep working when `/dev/input/eventN` numbering changes or a device (e.g. over
+//! Bluetooth) doesn't have a stable link under `/dev/input/by-path`.
+
+use evdev_rs::enums::{BusType, EventCode, InputProp, EV_ABS, EV_KEY};
+use evdev_rs::{Device as EvDevice, DeviceWrapper};
+#[allow(unused)]
+use log::{debug, error, info, trace, warn};
+use serde::Deserialize;
+use std::fmt;
+use std::os::unix::fs::FileTypeExt;
+use std::path::{Path, PathBuf};
+
+/// The directories of stable links to input devices.
+const LINK_DIRS: &[&str] = &["/dev/input/by-id", "/dev/input/by-path"];
+
+/// The tools a device may report being in contact with it, used to count fingers on touchpads.
+const TOOLS: &[EV_KEY] = &[
+    EV_KEY::BTN_TOOL_FINGER,
+    EV_KEY::BTN_TOOL_DOUBLETAP,
+    EV_KEY::BTN_TOOL_TRIPLETAP,
+    EV_KEY::BTN_TOOL_QUADTAP,
+    EV_KEY::BTN_TOOL_QUINTTAP,
+    EV_KEY::BTN_TOOL_PEN,
+    EV_KEY::BTN_TOOL_RUBBER,
+    EV_KEY::BTN_TOOL_BRUSH,
+    EV_KEY::BTN_TOOL_PENCIL,
+    EV_KEY::BTN_TOOL_AIRBRUSH,
+    EV_KEY::BTN_TOOL_MOUSE,
+    EV_KEY::BTN_TOOL_LENS,
+];
+
+/// The axes shown by `--list-devices`.
+const AXES: &[EV_ABS] = &[
+    EV_ABS::ABS_X,
+    EV_ABS::ABS_Y,
+    EV_ABS::ABS_MT_POSITION_X,
+    EV_ABS::ABS_MT_POSITION_Y,
+];
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Bus {
+    Usb,
+    Bluetooth,
+    I2c,
+    #[serde(alias = "i8042", alias = "serio")]
+    Ps2,
+    Spi,
+    Rmi,
+    Host,
+    Virtual,
+}
+
+impl Bus {
+    fn id(self) -> u16 {
+        let bus = match self {
+            Bus::Usb => BusType::BUS_USB,
+            Bus::Bluetooth => BusType::BUS_BLUETOOTH,
+            Bus::I2c => BusType::BUS_I2C,
+            Bus::Ps2 => BusType::BUS_I8042,
+            Bus::Spi => BusType::BUS_SPI,
+            Bus::Rmi => BusType::BUS_RMI,
+            Bus::Host => BusType::BUS_HOST,
+            Bus::Virtual => BusType::BUS_VIRTUAL,
+        };
+        bus as u16
+    }
+
+    fn from_id(id: u16) -> Option<Bus> {
+        [
+            Bus::Usb,
+            Bus::Bluetooth,
+            Bus::I2c,
+            Bus::Ps2,
+            Bus::Spi,
+            Bus::Rmi,
+            Bus::Host,
+            Bus::Virtual,
+        ]
+        .iter()
+        .copied()
+        .find(|bus| bus.id() == id)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Bus::Usb => "usb",
+            Bus::Bluetooth => "bluetooth",
+            Bus::I2c => "i2c",
+            Bus::Ps2 => "ps2",
+            Bus::Spi => "spi",
+            Bus::Rmi => "rmi",
+            Bus::Host => "host",
+            Bus::Virtual => "virtual",
+        }
+    }
+}
+
+/// The properties of an input device that can be used to select it.
+#[derive(Debug)]
+pub(crate) struct DeviceInfo {
+    pub path: String,
+    pub name: String,
+    pub vendor: u16,
+    pub product: u16,
+    pub bus: u16,
+    pub touchpad: bool,
+}
+
+impl DeviceInfo {
+    pub fn from_device(path: &str, device: &EvDevice) -> Self {
+        DeviceInfo {
+            path: path.to_owned(),
+            name: device.name().unwrap_or_default().to_owned(),
+            vendor: device.vendor_id(),
+            product: device.product_id(),
+            bus: device.bustype(),
+            touchpad: is_touchpad(device),
+        }
+    }
+
+    pub fn from_path(path: &str) -> Option<Self> {
+        match open(path) {
+            Ok(device) => Some(DeviceInfo::from_device(path, &device)),
+            Err(e) => {
+                debug!("{path}: {e}");
+                None
+            }
+        }
+    }
+}
+
+/// Opens the input device at `path`. evdev-rs spins forever trying to open e.g. a regular file, so
+/// anything other than a character device is rejected up front.
+pub(crate) fn open(path: &str) -> std::io::Result<EvDevice> {
+    if !std::fs::metadata(path)?.file_type().is_char_device() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "Not an input device",
+        ));
+    }
+    EvDevice::new_from_path(path)
+}
+
+/// Whether `device` is a multi-touch touchpad (speaking the type B multi-touch protocol we rely on),
+/// as opposed to e.g. a touchscreen or a mouse.
+fn is_touchpad(device: &EvDevice) -> bool {
+    device.has(EventCode::EV_ABS(EV_ABS::ABS_MT_SLOT))
+        && device.has(EventCode::EV_ABS(EV_ABS::ABS_MT_POSITION_X))
+        && device.has(EventCode::EV_ABS(EV_ABS::ABS_MT_POSITION_Y))
+        && device.has(EventCode::EV_KEY(EV_KEY::BTN_TOOL_FINGER))
+        && device.has_property(&InputProp::INPUT_PROP_POINTER)
+        && !device.has_property(&InputProp::INPUT_PROP_DIRECT)
+}
+
+/// The paths of all `/dev/input/event*` nodes, sorted.
+fn event_nodes() -> Vec<PathBuf> {
+    let entries = match std::fs::read_dir("/dev/input") {
+        Ok(entries) => entries,
+        Err(e) => {
+            debug!("/dev/input: {e}");
+            return Vec::new();
+        }
+    };
+
+    let mut paths: Vec<PathBuf> = entries
+        .flatten()
+        .filter(|entry| {
+            let file_name = entry.file_name();
+            file_name
+                .to_str()
+                .is_some_and(|name| name.starts_with("event"))
+        })
+        .map(|entry| entry.path())
+        .collect();
+    paths.sort();
+    paths
+}
+
+/// Look up every `/dev/input/event*` device we're able to open, sorted by path, skipping the nodes
+/// `filter` returns false for.
+pub(crate) fn enumerate<F: Fn(&Path) -> bool>(filter: F) -> Vec<DeviceInfo> {
+    let mut devices = Vec::new();
+    for path in event_nodes().into_iter().filter(|path| filter(path)) {
+        let path = path.to_string_lossy();
+        match open(&path) {
+            Ok(device) => devices.push(DeviceInfo::from_device(&path, &device)),
+            Err(e) => debug!("{path}: {e}"),
+        }
+    }
+    devices
+}
+
+/// The stable links under `/dev/input/by-id` and `/dev/input/by-path` pointing to `node`.
+fn links_to(node: &Path) -> Vec<PathBuf> {
+    let mut links = Vec::new();
+    for dir in LINK_DIRS {
+        let entries = match std::fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if std::fs::canonicalize(&path).is_ok_and(|target| target == node) {
+                links.push(path);
+            }
+        }
+    }
+    links.sort();
+    links
+}
+
+/// Print the details of all input devices, to help pick the one to configure.
+pub(crate) fn print_devices<W: std::io::Write>(target: &mut W) {
+    let nodes = event_nodes();
+    if nodes.is_empty() {
+        let _ = writeln!(target, "No input devices found");
+        return;
+    }
+
+    for node in nodes {
+        let path = node.to_string_lossy();
+        let device = match open(&path) {
+            Ok(device) => device,
+            Err(e) => {
+                let _ = writeln!(target, "{path}: {e}\n");
+                continue;
+            }
+        };
+        let info = DeviceInfo::from_device(&path, &device);
+
+        let _ = writeln!(
+            target,
+            "{path}: {}{}",
+            info.name,
+            if info.touchpad {
+                " (touchpad, supports gestures)"
+            } else {
+                ""
+            }
+        );
+        for link in links_to(&node) {
+            let _ = writeln!(target, "    link:    {}", link.display());
+        }
+        let bus = match Bus::from_id(info.bus) {
+            Some(bus) => bus.name().to_owned(),
+            None => format!("{:04x}", info.bus),
+        };
+        let _ = writeln!(
+            target,
+            "    id:      bus {bus}, vendor {:04x}, product {:04x}",
+            info.vendor, info.product
+        );
+        if let Some(slots) = device.abs_info(&EventCode::EV_ABS(EV_ABS::ABS_MT_SLOT)) {
+            let _ = writeln!(target, "    slots:   {}", slots.maximum + 1);
+        }
+        let tools: Vec<String> = TOOLS
+            .iter()
+            .map(|tool| EventCode::EV_KEY(*tool))
+            .filter(|code| device.has(*code))
+            .map(|code| code.to_string())
+            .collect();
+        if !tools.is_empty() {
+            let _ = writeln!(target, "    tools:   {}", tools.join(", "));
+        }
+        for axis in AXES {
+            let code = EventCode::EV_ABS(*axis);
+            if let Some(abs) = device.abs_info(&code) {
+                let _ = write!(
+                    target,
+                    "    axis:    {code} {}..{}",
+                    abs.minimum, abs.maximum
+                );
+                if abs.resolution > 0 {
+                    let _ = write!(target, " ({} units/mm)", abs.resolution);
+                }
+                let _ = writeln!(target);
+            }
+        }
+        let _ = writeln!(target);
+    }
+}
+
+/// Selects devices by their properties. Every property that's specified must match.
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) struct DeviceMatch {
+    /// A glob pattern the device name must match, e.g. `Apple Inc. Magic Trackpad*`
+    pub name: Option<String>,
+    pub vendor: Option<u16>,
+    pub product: Option<u16>,
+    pub bus: Option<Bus>,
+    /// Only match multi-touch touchpads
+    pub touchpad: bool,
+}
+
+impl DeviceMatch {
+    pub fn matches(&self, info: &DeviceInfo) -> bool {
+        self.name
+            .as_deref()
+            .is_none_or(|pattern| glob_match(pattern, &info.name))
+            && self.vendor.is_none_or(|vendor| vendor == info.vendor)
+            && self.product.is_none_or(|product| product == info.product)
+            && self.bus.is_none_or(|bus| bus.id() == info.bus)
+            && (!self.touchpad || info.touchpad)
+    }
+}
+
+impl fmt::Display for DeviceMatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut properties = Vec::new();
+        if let Some(name) = &self.name {
+            properties.push(format!("name \"{name}\""));
+        }
+        if let Some(vendor) = self.vendor {
+            properties.push(format!("vendor {vendor:04x}"));
+        }
+        if let Some(product) = self.product {
+            properties.push(format!("product {product:04x}"));
+        }
+        if let Some(bus) = self.bus {
+            properties.push(format!("bus {}", bus.name()));
+        }
+        if self.touchpad {
+            properties.push("touchpad".to_owned());
+        }
+        write!(f, "{}", properties.join(", "))
+    }
+}
+
+/// Match `text` against a shell-style glob `pattern`, where `*` matches any run of characters and
+/// `?` matches any single character.
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+
+    let (mut p, mut t) = (0, 0);
+    // Where to resume after the last `*` if the text stops matching what followed it
+    let mut backtrack = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                Some((star, star_t)) => {
+                    p = star + 1;
+                    t = star_t + 1;
+                    backtrack = Some((star, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glob_patterns() {
+        for (pattern, text, expected) in [
+            ("", "", true),
+            ("", "a", false),
+            ("*", "", true),
+            ("*", "Apple Inc. Magic Trackpad", true),
+            (
+                "Apple Inc. Magic Trackpad",
+                "Apple Inc. Magic Trackpad",
+                true,
+            ),
+            (
+                "Apple Inc. Magic Trackpad",
+                "Apple Inc. Magic Trackpad 2",
+                false,
+            ),
+            (
+                "Apple Inc. Magic Trackpad*",
+                "Apple Inc. Magic Trackpad 2",
+                true,
+            ),
+            (
+                "Apple Inc. Magic Trackpad*",
+                "Apple Inc. Magic Trackpa",
+                false,
+            ),
+            ("*Touchpad", "SynPS/2 Synaptics TouchPad", false),
+            ("*TouchPad", "SynPS/2 Synaptics TouchPad", true),
+            ("Syn*Pad", "SynPS/2 Synaptics TouchPad", true),
+            ("SynPS/? Synaptics*", "SynPS/2 Synaptics TouchPad", true),
+            ("?", "", false),
+            ("??", "ab", true),
+            ("??", "abc", false),
+            // The first `*` must give up characters for the rest of the pattern to match
+            ("*ab*ab", "xabyabzab", true),
+            ("*ab*ab", "xabyabz", false),
+            ("**", "abc", true),
+            ("a*?", "a", false),
+            ("a*?", "ab", true),
+            ("é*", "élan", true),
+        ] {
+            assert_eq!(
+                glob_match(pattern, text),
+                expected,
+                "{pattern:?} vs {text:?}"
+            );
+        }
+    }
+}
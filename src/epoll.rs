@@ -1,4 +1,4 @@
-use std::io::{Error, Result};
+use std::io::Result;
 use std::os::unix::prelude::*;
 use std::time::Duration;
 
@@ -17,12 +17,13 @@ macro_rules! syscall {
     ($fn: ident ( $($arg: expr),* $(,)* ) ) => {{
         let res = unsafe { libc::$fn($($arg, )*) };
         if res == -1 {
-            Err(Error::last_os_error())
+            Err(std::io::Error::last_os_error())
         } else {
             Ok(res)
         }
     }};
 }
+pub(crate) use syscall;
 
 impl Epoll {
     pub fn new() -> Result<Epoll> {
@@ -90,7 +91,10 @@ impl Epoll {
             self.fd.as_raw_fd(),
             self.events.as_mut_ptr(),
             self.events.capacity() as i32,
-            timeout.map(|d| d.as_millis() as i32).unwrap_or(-1),
+            // Round up so we don't wake (and spin) before the timeout has actually elapsed
+            timeout
+                .map(|d| d.as_micros().div_ceil(1000) as i32)
+                .unwrap_or(-1),
         ));
 
         let ready_count = *result.as_ref().unwrap_or(&0);
@@ -1,32 +1,765 @@
 use evdev_rs::enums::*;
-use evdev_rs::TimeVal;
+use evdev_rs::{DeviceWrapper, TimeVal};
 #[allow(unused)]
 use log::{debug, error, info, trace, warn};
-use serde::Deserialize;
-use serde_repr::*;
+use serde::{Deserialize, Deserializer};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
 
-/// The maximum travel before a tap is considered a swipe.
+/// The maximum travel (in device units) before a tap is considered a swipe.
 const MIN_SWIPE_DISTANCE: f64 = 300f64;
+/// The minimum change (in device units) in the fingers' average distance from their center before
+/// a gesture is considered a pinch.
+const MIN_PINCH_DISTANCE: f64 = 150f64;
+/// The minimum change (in degrees) in the angle of the fingers around their center before a
+/// gesture is considered a rotation.
+const MIN_ROTATE_ANGLE: f64 = 20f64;
 /// The maximum number of tools (fingers) that are initially tracked and reported on simultaneously.
 const INITIAL_SLOTS: usize = 5;
-/// How long before the event state resets
-const EVENT_TIMEOUT: f64 = 10_593_665_152f64;
-/// A new gesture (note: not a new report) will not be entertained in this timespan.
-const DEBOUNCE_TIME: f64 = 0.2f64;
+/// How long (in milliseconds) before the event state resets
+const EVENT_TIMEOUT: u64 = 10_593_665_152_000;
+/// A new gesture (note: not a new report) will not be entertained in this timespan (in
+/// milliseconds).
+const DEBOUNCE_TIME: u64 = 200;
+/// It's much easier to scroll side-to-side than up-down, so horizontal movement is favored by this
+/// factor when determining the direction of a swipe.
+const DIRECTION_BIAS: f64 = 1.05f64;
+/// The minimum average speed (in device units per second) of a fast swipe.
+const FAST_SWIPE_SPEED: f64 = 6000f64;
+/// The maximum average speed (in device units per second) of a slow swipe.
+const SLOW_SWIPE_SPEED: f64 = 2000f64;
+
+/// Per-device gesture recognition settings, configurable via `[device.settings]`.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub(crate) struct Settings {
+    /// Whether swipes are recognized in eight directions (including diagonals) instead of four.
+    pub diagonals: bool,
+    /// The angular width (in degrees) of each diagonal direction when `diagonals` is enabled.
+    /// The remainder of each quadrant is split between the two adjacent axes.
+    pub diagonal_width: f64,
+    /// How long (in milliseconds) after a tap the fingers may be placed down again to continue a
+    /// multi-tap sequence.
+    pub tap_interval: u32,
+    /// The maximum travel before a tap is considered a swipe.
+    pub min_swipe_distance: Length,
+    /// The minimum change in the fingers' average distance from their center before a gesture is
+    /// considered a pinch.
+    pub min_pinch_distance: Length,
+    /// The distance the fingers must travel for each update of a continuous [`Gesture::Slide`],
+    /// unless its binding specifies a `step` of its own.
+    pub slide_step: Length,
+    /// How close to an edge a swipe must start to be considered an edge swipe.
+    pub edge_size: Length,
+    /// How long (in milliseconds) after a gesture before another gesture is recognized.
+    pub debounce_time: u64,
+    /// How long (in milliseconds) without events before the touchpad state is reset.
+    pub event_timeout: u64,
+    /// The factor by which horizontal movement is favored over vertical movement when determining
+    /// the direction of a swipe.
+    pub direction_bias: f64,
+    /// The minimum distance per second the fingers must travel for a swipe to be considered fast.
+    pub fast_swipe_speed: Length,
+    /// The maximum distance per second the fingers may travel for a swipe to be considered slow.
+    pub slow_swipe_speed: Length,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            diagonals: false,
+            diagonal_width: 45f64,
+            tap_interval: 300,
+            min_swipe_distance: Length::Units(MIN_SWIPE_DISTANCE),
+            min_pinch_distance: Length::Units(MIN_PINCH_DISTANCE),
+            slide_step: Length::Units(200f64),
+            edge_size: Length::Percent(5f64),
+            debounce_time: DEBOUNCE_TIME,
+            event_timeout: EVENT_TIMEOUT,
+            direction_bias: DIRECTION_BIAS,
+            fast_swipe_speed: Length::Units(FAST_SWIPE_SPEED),
+            slow_swipe_speed: Length::Units(SLOW_SWIPE_SPEED),
+        }
+    }
+}
+
+/// A distance on the touchpad, configured either in raw device units (`300`), in millimetres
+/// (`"5mm"`), or as a percentage of the touchpad's width or height (`"10%"`).
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum Length {
+    Units(f64),
+    Millimeters(f64),
+    Percent(f64),
+}
+
+impl Length {
+    /// Converts the length to device units along an axis, if the axis information required to do
+    /// so is available.
+    pub fn to_units(self, axis: Option<&AxisInfo>) -> Option<f64> {
+        match (self, axis) {
+            (Length::Units(units), _) => Some(units),
+            (Length::Millimeters(mm), Some(axis)) if axis.resolution > 0 => {
+                Some(mm * f64::from(axis.resolution))
+            }
+            (Length::Percent(percent), Some(axis)) => {
+                Some(percent / 100f64 * f64::from(axis.max - axis.min))
+            }
+            _ => None,
+        }
+    }
+
+    /// Converts a length that isn't along a particular axis to device units, using the average of
+    /// both axes.
+    pub fn to_distance(self, dimensions: Option<&Dimensions>) -> Option<f64> {
+        let x = self.to_units(dimensions.map(|d| &d.x))?;
+        let y = self.to_units(dimensions.map(|d| &d.y))?;
+        Some((x + y) / 2f64)
+    }
+}
+
+// Lengths are compared by unit first, so that they can be part of a `GesturePattern` key.
+impl Ord for Length {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let key = |length: &Length| match *length {
+            Length::Units(value) => (0, value),
+            Length::Millimeters(value) => (1, value),
+            Length::Percent(value) => (2, value),
+        };
+        let (unit, value) = key(self);
+        let (other_unit, other_value) = key(other);
+        unit.cmp(&other_unit)
+            .then_with(|| value.total_cmp(&other_value))
+    }
+}
+
+impl PartialOrd for Length {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Length {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Length {}
+
+impl FromStr for Length {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (value, length): (&str, fn(f64) -> Length) = if let Some(mm) = s.strip_suffix("mm") {
+            (mm, Length::Millimeters)
+        } else if let Some(percent) = s.strip_suffix('%') {
+            (percent, Length::Percent)
+        } else {
+            (s, Length::Units)
+        };
+
+        match value.trim().parse::<f64>() {
+            Ok(value) if value >= 0f64 => Ok(length(value)),
+            _ => Err(format!(
+                "Invalid length {s:?}, expected a number of device units, millimetres (\"5mm\"), \
+                or percent (\"10%\")"
+            )),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Length {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum LengthValue {
+            Units(f64),
+            String(String),
+        }
+
+        match LengthValue::deserialize(deserializer)? {
+            LengthValue::Units(units) if units >= 0f64 => Ok(Length::Units(units)),
+            LengthValue::Units(units) => {
+                Err(format!("Invalid length {units}, must not be negative"))
+            }
+            LengthValue::String(s) => s.parse(),
+        }
+        .map_err(serde::de::Error::custom)
+    }
+}
+
+/// The range of values the touchpad reports along one axis.
+#[derive(Clone, Debug)]
+pub(crate) struct AxisInfo {
+    pub min: i32,
+    pub max: i32,
+    /// The number of units per millimetre, or zero if unknown
+    pub resolution: i32,
+}
+
+/// The range of positions the touchpad reports, as advertised by the device.
+#[derive(Clone, Debug)]
+pub(crate) struct Dimensions {
+    pub x: AxisInfo,
+    pub y: AxisInfo,
+}
+
+impl Dimensions {
+    /// Queries the range of the overall position axes, falling back to those of the per-tool
+    /// multitouch axes for devices that only report the latter.
+    pub fn from_device<D: DeviceWrapper>(device: &D) -> Option<Self> {
+        let axis_info = |codes: [EV_ABS; 2]| {
+            codes
+                .iter()
+                .find_map(|code| device.abs_info(&EventCode::EV_ABS(*code)))
+                .map(|info| AxisInfo {
+                    min: info.minimum,
+                    max: info.maximum,
+                    resolution: info.resolution,
+                })
+        };
+
+        Some(Self {
+            x: axis_info([EV_ABS::ABS_X, EV_ABS::ABS_MT_POSITION_X])?,
+            y: axis_info([EV_ABS::ABS_Y, EV_ABS::ABS_MT_POSITION_Y])?,
+        })
+    }
+}
+
+/// The distances from [`Settings`], converted to device units.
+#[derive(Debug, Default)]
+struct Thresholds {
+    min_swipe_distance: f64,
+    min_pinch_distance: f64,
+    slide_step: f64,
+    /// Speeds in device units per second
+    fast_swipe_speed: f64,
+    slow_swipe_speed: f64,
+    /// The size of the left and right edges
+    edge_x: f64,
+    /// The size of the top and bottom edges
+    edge_y: f64,
+}
+
+impl Thresholds {
+    fn new(settings: &Settings, dimensions: Option<&Dimensions>) -> Self {
+        let defaults = Settings::default();
+        let x = dimensions.map(|d| &d.x);
+        let y = dimensions.map(|d| &d.y);
+
+        let resolve = |name: &str, length: Length, default: Length| {
+            length.to_distance(dimensions).unwrap_or_else(|| {
+                warn!("Unable to convert {name} {length:?} to device units, using {default:?}");
+                default.to_distance(dimensions).unwrap_or_default()
+            })
+        };
+
+        Self {
+            min_swipe_distance: resolve(
+                "min_swipe_distance",
+                settings.min_swipe_distance,
+                defaults.min_swipe_distance,
+            ),
+            min_pinch_distance: resolve(
+                "min_pinch_distance",
+                settings.min_pinch_distance,
+                defaults.min_pinch_distance,
+            ),
+            slide_step: resolve("slide_step", settings.slide_step, defaults.slide_step),
+            fast_swipe_speed: resolve(
+                "fast_swipe_speed",
+                settings.fast_swipe_speed,
+                defaults.fast_swipe_speed,
+            ),
+            slow_swipe_speed: resolve(
+                "slow_swipe_speed",
+                settings.slow_swipe_speed,
+                defaults.slow_swipe_speed,
+            ),
+            // Edges are only detected if the touchpad's dimensions are known
+            edge_x: settings
+                .edge_size
+                .to_units(x)
+                .or_else(|| defaults.edge_size.to_units(x))
+                .unwrap_or_default(),
+            edge_y: settings
+                .edge_size
+                .to_units(y)
+                .or_else(|| defaults.edge_size.to_units(y))
+                .unwrap_or_default(),
+        }
+    }
+}
 
 pub(crate) struct EventLoop {
     report: SynReport,
     state: TouchpadState,
+    /// The durations (in milliseconds) of the bound [`Gesture::Hold`] gestures for each number of
+    /// fingers (or for any number of fingers, as `None`), in ascending order.
+    hold_durations: BTreeMap<Option<Fingers>, Vec<u32>>,
+    hold: Option<HoldTimer>,
+    /// The highest bound [`Gesture::Tap`] count for each number of fingers (or for any number of
+    /// fingers, as `None`), if more than one.
+    max_taps: BTreeMap<Option<Fingers>, u32>,
+    pending_tap: Option<PendingTap>,
+    /// The step (in device units) of the [`Gesture::Slide`] bound for each number of fingers and
+    /// direction, with `None` for a binding that matches any number of fingers or any direction.
+    /// If several slides are bound for the same fingers and direction, the smallest step is used.
+    slide_steps: BTreeMap<(Option<Fingers>, Option<Direction>), f64>,
+    slide: Option<SlideState>,
+}
+
+/// Tracks the travel of the fingers during a continuous gesture.
+#[derive(Debug)]
+struct SlideState {
+    fingers: Fingers,
+    /// The position from which travel towards the next step is measured
+    anchor: Position,
+    /// The direction of the first step, if the slide has begun
+    direction: Option<Direction>,
+    start: Instant,
+    /// The distance (in device units) of each step, once the slide has begun
+    step: f64,
+    /// The number of steps travelled so far
+    steps: u32,
+}
+
+/// A tap (or sequence of taps) that has been held back because it may yet be followed by another.
+#[derive(Debug)]
+struct PendingTap {
+    fingers: Fingers,
+    count: u32,
+    deadline: Instant,
+    /// The distance travelled during the last tap
+    distance: f64,
+    /// How long the fingers were down during the last tap
+    duration: Duration,
+}
+
+impl PendingTap {
+    fn into_event(self) -> GestureEvent {
+        GestureEvent {
+            gesture: Gesture::Tap {
+                fingers: self.fingers,
+                count: self.count,
+            },
+            distance: self.distance,
+            duration: self.duration,
+        }
+    }
+}
+
+/// Tracks how long the current set of fingers has been held in place.
+#[derive(Debug)]
+struct HoldTimer {
+    fingers: Fingers,
+    start: Instant,
+    /// The durations (in milliseconds) of the holds bound for these fingers, in ascending order.
+    durations: Vec<u32>,
+    /// The number of entries in `durations` that have already elapsed.
+    fired: usize,
+    /// Set if the fingers have moved too far to be considered held in place.
+    cancelled: bool,
 }
 
 impl EventLoop {
-    pub fn new() -> Self {
+    pub fn new(settings: Settings, dimensions: Option<Dimensions>) -> Self {
         Self {
             report: Default::default(),
             state: TouchpadState {
+                thresholds: Thresholds::new(&settings, dimensions.as_ref()),
+                settings,
+                dimensions,
                 slot_states: vec![None; INITIAL_SLOTS],
                 ..Default::default()
             },
+            hold_durations: BTreeMap::new(),
+            hold: None,
+            max_taps: BTreeMap::new(),
+            pending_tap: None,
+            slide_steps: BTreeMap::new(),
+            slide: None,
+        }
+    }
+
+    /// Informs the event loop of a gesture that has been bound to an action. This is required for
+    /// gestures that are recognized while the fingers are still on the touchpad.
+    pub fn register(&mut self, gesture: &GesturePattern) {
+        match gesture {
+            GesturePattern::Hold { fingers, duration } => {
+                let durations = self.hold_durations.entry(*fingers).or_default();
+                if let Err(i) = durations.binary_search(duration) {
+                    durations.insert(i, *duration);
+                }
+            }
+            GesturePattern::Tap { fingers, count } if *count > 1 => {
+                let max_taps = self.max_taps.entry(*fingers).or_default();
+                *max_taps = (*max_taps).max(*count);
+            }
+            GesturePattern::Slide {
+                fingers,
+                direction,
+                step,
+                ..
+            } => {
+                let default = self.state.thresholds.slide_step;
+                let step = match step {
+                    Some(step) => step
+                        .to_distance(self.state.dimensions.as_ref())
+                        .unwrap_or_else(|| {
+                            warn!("Unable to convert slide step {step:?} to device units");
+                            default
+                        }),
+                    None => default,
+                };
+                let slide_step = self
+                    .slide_steps
+                    .entry((*fingers, direction.clone()))
+                    .or_insert(step);
+                *slide_step = slide_step.min(step);
+            }
+            GesturePattern::Swipe {
+                fingers,
+                click: true,
+                ..
+            } => {
+                self.state.click_fingers.insert(*fingers);
+            }
+            _ => {}
+        }
+    }
+
+    /// Forgets all gestures previously passed to [`register()`](Self::register).
+    pub fn unregister_all(&mut self) {
+        self.hold_durations.clear();
+        self.max_taps.clear();
+        self.slide_steps.clear();
+        self.state.click_fingers.clear();
+    }
+
+    /// Returns how long until [`check_timers()`](Self::check_timers) should next be called, or
+    /// `None` if there is no pending timer.
+    pub fn timeout(&self) -> Option<Duration> {
+        let deadline = match (self.hold_deadline(), self.tap_deadline()) {
+            (Some(hold), Some(tap)) => hold.min(tap),
+            (hold, tap) => hold.or(tap)?,
+        };
+        Some(deadline.saturating_duration_since(Instant::now()))
+    }
+
+    /// Returns any gestures whose timers have elapsed since the last report was received.
+    pub fn check_timers(&mut self) -> Vec<GestureEvent> {
+        let mut gestures = Vec::new();
+        let now = Instant::now();
+
+        if matches!(self.hold_deadline(), Some(deadline) if now >= deadline) {
+            // A hold can't also be the next tap of a sequence
+            gestures.extend(self.pending_tap.take().map(PendingTap::into_event));
+            let hold = self.hold.as_mut().unwrap();
+            let duration = hold.durations[hold.fired];
+            hold.fired += 1;
+            debug!("hold detected");
+            gestures.push(GestureEvent {
+                gesture: Gesture::Hold {
+                    fingers: hold.fingers,
+                    duration,
+                },
+                distance: self.state.distance(),
+                duration: now - hold.start,
+            });
+        }
+
+        if matches!(self.tap_deadline(), Some(deadline) if now >= deadline) {
+            let tap = self.pending_tap.take().unwrap();
+            debug!("{} tap sequence completed", tap.count);
+            gestures.push(tap.into_event());
+        }
+
+        gestures
+    }
+
+    fn tap_deadline(&self) -> Option<Instant> {
+        let tap = self.pending_tap.as_ref()?;
+        // Once the same fingers have been placed back down in time, the sequence is only resolved
+        // when they are lifted again (or stop looking like a tap). Fewer fingers can't hold it up
+        // past the deadline.
+        if self.state.last_finger.is_some() && self.state.max_fingers == Some(tap.fingers) {
+            return None;
+        }
+        Some(tap.deadline)
+    }
+
+    /// Resolves the pending tap sequence as soon as the fingers on the touchpad can no longer
+    /// continue it, rather than waiting for them to be lifted.
+    fn interrupt_taps(&mut self) -> Option<GestureEvent> {
+        let tap = self.pending_tap.as_ref()?;
+        let state = &self.state;
+        state.last_finger?;
+
+        let interrupted = state.max_fingers > Some(tap.fingers)
+            || state.clicked
+            || state.distance() >= state.thresholds.min_swipe_distance
+            || matches!(&self.hold, Some(hold) if hold.fired > 0)
+            || matches!(&self.slide, Some(slide) if slide.direction.is_some());
+        if !interrupted {
+            return None;
+        }
+
+        debug!("{} tap sequence interrupted", tap.count);
+        self.pending_tap.take().map(PendingTap::into_event)
+    }
+
+    /// Combines consecutive taps into a single multi-tap gesture, holding back each tap for as
+    /// long as it may still be followed by another.
+    fn sequence_taps(&mut self, event: GestureEvent) -> Vec<GestureEvent> {
+        let mut gestures = Vec::new();
+        let pending = self.pending_tap.take();
+
+        let fingers = match event.gesture {
+            Gesture::Tap { fingers, .. } => fingers,
+            _ => {
+                gestures.extend(pending.map(PendingTap::into_event));
+                gestures.push(event);
+                return gestures;
+            }
+        };
+
+        let count = match pending {
+            Some(tap) if tap.fingers == fingers => tap.count + 1,
+            Some(tap) => {
+                gestures.push(tap.into_event());
+                1
+            }
+            None => 1,
+        };
+
+        if count >= self.max_taps(fingers) {
+            gestures.push(GestureEvent {
+                gesture: Gesture::Tap { fingers, count },
+                ..event
+            });
+        } else {
+            debug!("Waiting for tap {}", count + 1);
+            let interval = Duration::from_millis(u64::from(self.state.settings.tap_interval));
+            self.pending_tap = Some(PendingTap {
+                fingers,
+                count,
+                deadline: Instant::now() + interval,
+                distance: event.distance,
+                duration: event.duration,
+            });
+        }
+
+        gestures
+    }
+
+    /// The highest bound tap count for the given number of fingers.
+    fn max_taps(&self, fingers: Fingers) -> u32 {
+        let specific = self.max_taps.get(&Some(fingers)).copied();
+        let any = self.max_taps.get(&None).copied();
+        specific.max(any).unwrap_or(1)
+    }
+
+    /// The durations of the holds bound for the given number of fingers, in ascending order.
+    fn hold_durations(&self, fingers: Fingers) -> Vec<u32> {
+        let mut durations: Vec<u32> = [Some(fingers), None]
+            .iter()
+            .filter_map(|fingers| self.hold_durations.get(fingers))
+            .flatten()
+            .copied()
+            .collect();
+        durations.sort_unstable();
+        durations.dedup();
+        durations
+    }
+
+    fn hold_deadline(&self) -> Option<Instant> {
+        match &self.hold {
+            Some(hold) if !hold.cancelled => {
+                let duration = hold.durations.get(hold.fired)?;
+                Some(hold.start + Duration::from_millis(u64::from(*duration)))
+            }
+            _ => None,
+        }
+    }
+
+    /// Reports the progress of a continuous gesture after a report has been processed.
+    fn update_slide(&mut self) -> Vec<GestureEvent> {
+        let mut gestures = Vec::new();
+        if self.slide_steps.is_empty() {
+            return gestures;
+        }
+
+        let state = &self.state;
+        let fingers = match state.last_finger {
+            // Ignore the change in position as fingers are lifted, as with regular gestures
+            Some(fingers) if state.max_fingers == Some(fingers) => fingers,
+            Some(_) => return gestures,
+            None => {
+                if let Some(SlideState {
+                    fingers,
+                    direction: Some(direction),
+                    start,
+                    step,
+                    steps,
+                    ..
+                }) = self.slide.take()
+                {
+                    debug!("slide ended");
+                    gestures.push(GestureEvent {
+                        gesture: Gesture::Slide {
+                            fingers,
+                            direction,
+                            phase: Phase::End,
+                        },
+                        distance: f64::from(steps) * step,
+                        duration: start.elapsed(),
+                    });
+                }
+                return gestures;
+            }
+        };
+        if !self
+            .slide_steps
+            .keys()
+            .any(|(f, _)| f.is_none_or(|f| f == fingers))
+        {
+            return gestures;
+        }
+        let position = match state.end_xy.as_ref().or(state.start_xy.as_ref()) {
+            Some(position) => position.clone(),
+            None => return gestures,
+        };
+
+        let step = match &self.slide {
+            Some(slide) if slide.fingers == fingers => match slide.direction {
+                Some(_) => slide.step,
+                None => {
+                    // The slide only begins once the fingers head in a direction a slide is bound
+                    // for, leaving travel in any other direction to be recognized as a swipe.
+                    let direction = get_direction(&slide.anchor, &position, &state.settings);
+                    match self.slide_step(fingers, &direction) {
+                        Some(step) => step,
+                        None => return gestures,
+                    }
+                }
+            },
+            _ => {
+                self.slide = Some(SlideState {
+                    fingers,
+                    anchor: position,
+                    direction: None,
+                    start: Instant::now(),
+                    step: 0f64,
+                    steps: 0,
+                });
+                return gestures;
+            }
+        };
+
+        let slide = self.slide.as_mut().unwrap();
+        let distance = get_distance(&slide.anchor, &position);
+        if distance < step {
+            return gestures;
+        }
+
+        let direction = get_direction(&slide.anchor, &position, &state.settings);
+        slide.step = step;
+        // Move the anchor by exactly one step towards the current position so that any excess
+        // travel counts towards the next step.
+        let ratio = step / distance;
+        slide.anchor = pos(
+            slide.anchor.x + (f64::from(position.x - slide.anchor.x) * ratio) as i32,
+            slide.anchor.y + (f64::from(position.y - slide.anchor.y) * ratio) as i32,
+        );
+
+        slide.steps += 1;
+        let distance = f64::from(slide.steps) * step;
+        let duration = slide.start.elapsed();
+
+        if slide.direction.is_none() {
+            debug!("slide began");
+            slide.direction = Some(direction.clone());
+            gestures.push(GestureEvent {
+                gesture: Gesture::Slide {
+                    fingers,
+                    direction: direction.clone(),
+                    phase: Phase::Begin,
+                },
+                distance,
+                duration,
+            });
+            // A continuous gesture can't also be a hold
+            if let Some(hold) = &mut self.hold {
+                hold.cancelled = true;
+            }
+        }
+        gestures.push(GestureEvent {
+            gesture: Gesture::Slide {
+                fingers,
+                direction,
+                phase: Phase::Update,
+            },
+            distance,
+            duration,
+        });
+
+        gestures
+    }
+
+    /// The smallest step of the slides bound for the given fingers moving in `direction`, if any.
+    fn slide_step(&self, fingers: Fingers, direction: &Direction) -> Option<f64> {
+        self.slide_steps
+            .iter()
+            .filter(|((f, d), _)| {
+                f.is_none_or(|f| f == fingers) && d.as_ref().is_none_or(|d| d == direction)
+            })
+            .map(|(_, step)| *step)
+            .min_by(f64::total_cmp)
+    }
+
+    /// Starts, restarts, or cancels the hold timer after a report has been processed.
+    fn update_hold(&mut self) {
+        if self.hold_durations.is_empty() {
+            return;
+        }
+
+        let fingers = match self.state.last_finger {
+            Some(fingers) => fingers,
+            None => {
+                self.hold = None;
+                return;
+            }
+        };
+        let moved = match (&self.state.start_xy, &self.state.end_xy) {
+            // Pressing down to click-and-drag isn't holding still either
+            _ if self.state.clicked => true,
+            (Some(start_xy), Some(end_xy)) => {
+                get_distance(start_xy, end_xy) >= self.state.thresholds.min_swipe_distance
+            }
+            _ => false,
+        };
+
+        match &mut self.hold {
+            Some(hold) if hold.fingers == fingers => {
+                if moved && !hold.cancelled {
+                    debug!("hold cancelled");
+                    hold.cancelled = true;
+                }
+            }
+            _ => {
+                // Without any holds bound for these fingers, the timer never fires
+                self.hold = Some(HoldTimer {
+                    fingers,
+                    start: Instant::now(),
+                    durations: self.hold_durations(fingers),
+                    fired: 0,
+                    cancelled: moved,
+                });
+            }
         }
     }
 
@@ -35,13 +768,29 @@ impl EventLoop {
         time: TimeVal,
         event_code: EventCode,
         event_value: i32,
-    ) -> Option<Gesture> {
+    ) -> Vec<GestureEvent> {
         match event_code {
             EventCode::EV_SYN(EV_SYN::SYN_REPORT) => {
                 debug!("Processing report with {} events", self.report.events.len());
+                let held = matches!(&self.hold, Some(hold) if hold.fired > 0);
+                let slid = matches!(&self.slide, Some(slide) if slide.direction.is_some());
+                self.state.awaiting_tap = self.pending_tap.as_ref().map(|tap| tap.fingers);
                 let result = self.state.update(&mut self.report);
                 self.report.events.clear();
-                result
+                self.update_hold();
+                let slide = self.update_slide();
+                let mut gestures: Vec<GestureEvent> = self.interrupt_taps().into_iter().collect();
+                gestures.extend(slide);
+                match result {
+                    Some(_) if held || slid => {
+                        // The fingers being lifted after a hold or a continuous gesture is not a
+                        // gesture of its own
+                        debug!("Gesture ignored after hold or slide");
+                    }
+                    Some(event) => gestures.extend(self.sequence_taps(event)),
+                    None => {}
+                }
+                gestures
             }
             EventCode::EV_ABS(code) => {
                 trace!("{code:?}: {event_value}");
@@ -51,7 +800,7 @@ impl EventLoop {
                     event: EvdevEvent::Abs(code),
                     value: event_value,
                 });
-                None
+                Vec::new()
             }
             EventCode::EV_KEY(code) => {
                 trace!("{code:?}: {event_value}");
@@ -61,17 +810,17 @@ impl EventLoop {
                     event: EvdevEvent::Key(code),
                     value: event_value,
                 });
-                None
+                Vec::new()
             }
             _ => {
                 trace!("<IGNORED> {event_code:?}: {event_value}");
-                None
+                Vec::new()
             }
         }
     }
 }
 
-#[derive(Deserialize, Debug, PartialEq, PartialOrd, Eq, Ord)]
+#[derive(Deserialize, Clone, Debug, PartialEq, PartialOrd, Eq, Ord)]
 pub(crate) enum Direction {
     #[serde(alias = "up")]
     Up,
@@ -81,15 +830,129 @@ pub(crate) enum Direction {
     Left,
     #[serde(alias = "right")]
     Right,
+    #[serde(alias = "up-left")]
+    UpLeft,
+    #[serde(alias = "up-right")]
+    UpRight,
+    #[serde(alias = "down-left")]
+    DownLeft,
+    #[serde(alias = "down-right")]
+    DownRight,
+}
+
+impl Direction {
+    /// The name of the direction, as used in the configuration file.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Direction::Up => "up",
+            Direction::Down => "down",
+            Direction::Left => "left",
+            Direction::Right => "right",
+            Direction::UpLeft => "up-left",
+            Direction::UpRight => "up-right",
+            Direction::DownLeft => "down-left",
+            Direction::DownRight => "down-right",
+        }
+    }
 }
 
-#[repr(u8)]
-#[derive(Deserialize_repr, Clone, Debug, PartialEq, PartialOrd, Copy, Eq, Ord)]
+/// The stage of a continuous gesture
+#[derive(Deserialize, Clone, Debug, Default, PartialEq, PartialOrd, Eq, Ord)]
+pub(crate) enum Phase {
+    /// The fingers have travelled far enough for the gesture to begin
+    #[serde(alias = "begin")]
+    Begin,
+    /// The fingers have travelled another step
+    #[serde(alias = "update")]
+    #[default]
+    Update,
+    /// The fingers have been lifted
+    #[serde(alias = "end")]
+    End,
+}
+
+/// The edge of the touchpad a swipe started from
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, PartialOrd, Eq, Ord)]
+pub(crate) enum Edge {
+    #[serde(alias = "left")]
+    Left,
+    #[serde(alias = "right")]
+    Right,
+    #[serde(alias = "top")]
+    Top,
+    #[serde(alias = "bottom")]
+    Bottom,
+}
+
+/// How fast the fingers travelled during a swipe
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, PartialOrd, Eq, Ord)]
+pub(crate) enum Speed {
+    #[serde(alias = "fast")]
+    Fast,
+    #[serde(alias = "slow")]
+    Slow,
+}
+
+#[derive(Deserialize, Debug, PartialEq, PartialOrd, Eq, Ord)]
+pub(crate) enum PinchDirection {
+    /// The fingers were brought together
+    #[serde(alias = "in")]
+    In,
+    /// The fingers were spread apart
+    #[serde(alias = "out")]
+    Out,
+}
+
+#[derive(Deserialize, Debug, PartialEq, PartialOrd, Eq, Ord)]
+pub(crate) enum RotateDirection {
+    #[serde(alias = "clockwise")]
+    Clockwise,
+    #[serde(alias = "counterclockwise", alias = "counter-clockwise")]
+    CounterClockwise,
+}
+
+#[derive(Clone, Debug, PartialEq, PartialOrd, Copy, Eq, Ord)]
 pub(crate) enum Fingers {
-    One = 1,
-    Two = 2,
-    Three = 3,
-    Four = 4,
+    One,
+    Two,
+    Three,
+    Four,
+    Five,
+    /// Six or more fingers, which can only be detected by counting multitouch slots
+    Many(u8),
+}
+
+impl Fingers {
+    pub fn from_count(count: usize) -> Option<Self> {
+        Some(match count {
+            0 => return None,
+            1 => Fingers::One,
+            2 => Fingers::Two,
+            3 => Fingers::Three,
+            4 => Fingers::Four,
+            5 => Fingers::Five,
+            n => Fingers::Many(n.min(u8::MAX as usize) as u8),
+        })
+    }
+
+    pub fn count(self) -> usize {
+        match self {
+            Fingers::One => 1,
+            Fingers::Two => 2,
+            Fingers::Three => 3,
+            Fingers::Four => 4,
+            Fingers::Five => 5,
+            Fingers::Many(n) => n as usize,
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Fingers {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let count = u8::deserialize(deserializer)?;
+        Fingers::from_count(count as usize)
+            .ok_or_else(|| serde::de::Error::custom("fingers must be at least 1"))
+    }
 }
 
 #[derive(Debug, PartialEq)]
@@ -115,18 +978,304 @@ struct SynReport {
 }
 
 /// A result derived from one or more [`SynReport`] instances in a stream.
-#[derive(Deserialize)]
-#[serde(tag = "type")]
-#[serde(rename_all = "lowercase")]
 #[derive(Debug, PartialEq, PartialOrd, Eq, Ord)]
 pub(crate) enum Gesture {
     Tap {
         fingers: Fingers,
+        /// The number of taps in quick succession
+        count: u32,
     },
     Swipe {
         fingers: Fingers,
         direction: Direction,
+        /// Set if the swipe started at the edge of the touchpad and moved away from it
+        edge: Option<Edge>,
+        /// Set if the touchpad was physically clicked during the swipe
+        click: bool,
+        /// Set if the swipe was particularly fast or slow
+        speed: Option<Speed>,
+    },
+    Pinch {
+        fingers: Fingers,
+        direction: PinchDirection,
+    },
+    Rotate {
+        fingers: Fingers,
+        direction: RotateDirection,
+    },
+    Hold {
+        fingers: Fingers,
+        /// How long (in milliseconds) the fingers must be held in place
+        duration: u32,
+    },
+    /// A continuous movement, reported as it happens rather than when the fingers are lifted
+    Slide {
+        fingers: Fingers,
+        /// The direction of the latest step, or of the first step when beginning or ending
+        direction: Direction,
+        phase: Phase,
+    },
+}
+
+/// A gesture as bound in the configuration file. Leaving out `fingers` or `direction` matches any
+/// number of fingers or any direction, respectively, and `Any` matches every gesture.
+#[derive(Deserialize)]
+#[serde(tag = "type")]
+#[serde(rename_all = "lowercase")]
+#[derive(Debug, PartialEq, PartialOrd, Eq, Ord)]
+pub(crate) enum GesturePattern {
+    Tap {
+        fingers: Option<Fingers>,
+        #[serde(default = "default_tap_count")]
+        count: u32,
+    },
+    Swipe {
+        fingers: Option<Fingers>,
+        direction: Option<Direction>,
+        /// Only matches swipes starting at this edge, if set
+        #[serde(default)]
+        edge: Option<Edge>,
+        /// Matches swipes with a physical click (i.e. click-and-drag) instead of regular swipes
+        #[serde(default)]
+        click: bool,
+        /// Only matches swipes of this speed, if set
+        #[serde(default)]
+        speed: Option<Speed>,
+        /// Only matches swipes at least this long, if set
+        #[serde(default)]
+        min_distance: Option<Length>,
+        /// Only matches swipes at most this long, if set
+        #[serde(default)]
+        max_distance: Option<Length>,
+    },
+    Pinch {
+        fingers: Option<Fingers>,
+        direction: Option<PinchDirection>,
+    },
+    Rotate {
+        fingers: Option<Fingers>,
+        direction: Option<RotateDirection>,
+    },
+    Hold {
+        fingers: Option<Fingers>,
+        duration: u32,
+    },
+    Slide {
+        fingers: Option<Fingers>,
+        direction: Option<Direction>,
+        #[serde(default)]
+        phase: Phase,
+        /// The distance the fingers must travel for each step, instead of the `slide_step` setting
+        #[serde(default)]
+        step: Option<Length>,
     },
+    Any {
+        fingers: Option<Fingers>,
+    },
+}
+
+fn default_tap_count() -> u32 {
+    1
+}
+
+/// Whether an optional property of a [`GesturePattern`] matches the value of the gesture.
+fn matches<T: PartialEq>(pattern: &Option<T>, value: &T) -> bool {
+    pattern.as_ref().map(|p| p == value).unwrap_or(true)
+}
+
+impl GesturePattern {
+    /// Whether the pattern matches a gesture performed on a touchpad of the given `dimensions`.
+    pub fn matches(&self, event: &GestureEvent, dimensions: Option<&Dimensions>) -> bool {
+        use GesturePattern as P;
+
+        // A distance that can't be converted to device units can't be compared against either
+        let within = |min: &Option<Length>, max: &Option<Length>| {
+            let min = min.map(|min| min.to_distance(dimensions).unwrap_or(f64::INFINITY));
+            let max = max.map(|max| max.to_distance(dimensions).unwrap_or(f64::NEG_INFINITY));
+            min.map(|min| event.distance >= min).unwrap_or(true)
+                && max.map(|max| event.distance <= max).unwrap_or(true)
+        };
+
+        match (self, &event.gesture) {
+            (P::Any { fingers }, gesture) => matches(fingers, &gesture.fingers()),
+            (
+                P::Tap { fingers, count },
+                Gesture::Tap {
+                    fingers: f,
+                    count: c,
+                },
+            ) => matches(fingers, f) && count == c,
+            (
+                P::Swipe {
+                    fingers,
+                    direction,
+                    edge,
+                    click,
+                    speed,
+                    min_distance,
+                    max_distance,
+                },
+                Gesture::Swipe {
+                    fingers: f,
+                    direction: d,
+                    edge: e,
+                    click: c,
+                    speed: s,
+                },
+            ) => {
+                matches(fingers, f)
+                    && matches(direction, d)
+                    && (edge.is_none() || edge == e)
+                    && click == c
+                    && (speed.is_none() || speed == s)
+                    && within(min_distance, max_distance)
+            }
+            (
+                P::Pinch { fingers, direction },
+                Gesture::Pinch {
+                    fingers: f,
+                    direction: d,
+                },
+            ) => matches(fingers, f) && matches(direction, d),
+            (
+                P::Rotate { fingers, direction },
+                Gesture::Rotate {
+                    fingers: f,
+                    direction: d,
+                },
+            ) => matches(fingers, f) && matches(direction, d),
+            (
+                P::Hold { fingers, duration },
+                Gesture::Hold {
+                    fingers: f,
+                    duration: d,
+                },
+            ) => matches(fingers, f) && duration == d,
+            (
+                P::Slide {
+                    fingers,
+                    direction,
+                    phase,
+                    ..
+                },
+                Gesture::Slide {
+                    fingers: f,
+                    direction: d,
+                    phase: p,
+                },
+            ) => matches(fingers, f) && matches(direction, d) && phase == p,
+            _ => false,
+        }
+    }
+
+    /// How narrowly the pattern matches gestures, used to pick the most specific of several
+    /// matching bindings: the number of properties it names, with ties going to the pattern that
+    /// names the number of fingers.
+    pub fn specificity(&self) -> (usize, bool) {
+        let (kind, fingers, others) = match self {
+            GesturePattern::Any { fingers } => (0, fingers, 0),
+            GesturePattern::Tap { fingers, .. } | GesturePattern::Hold { fingers, .. } => {
+                (1, fingers, 1)
+            }
+            GesturePattern::Swipe {
+                fingers,
+                direction,
+                edge,
+                click,
+                speed,
+                min_distance,
+                max_distance,
+            } => (
+                1,
+                fingers,
+                direction.is_some() as usize
+                    + edge.is_some() as usize
+                    + *click as usize
+                    + speed.is_some() as usize
+                    + min_distance.is_some() as usize
+                    + max_distance.is_some() as usize,
+            ),
+            GesturePattern::Pinch { fingers, direction } => {
+                (1, fingers, direction.is_some() as usize)
+            }
+            GesturePattern::Rotate { fingers, direction } => {
+                (1, fingers, direction.is_some() as usize)
+            }
+            GesturePattern::Slide {
+                fingers, direction, ..
+            } => (1, fingers, 1 + direction.is_some() as usize),
+        };
+        (
+            kind + fingers.is_some() as usize + others,
+            fingers.is_some(),
+        )
+    }
+}
+
+/// A recognized [`Gesture`] along with measurements of how it was performed.
+#[derive(Debug)]
+pub(crate) struct GestureEvent {
+    pub gesture: Gesture,
+    /// How far (in device units) the fingers travelled. For a pinch this is the change in the
+    /// fingers' distance from their center and for a rotation the distance travelled around it.
+    pub distance: f64,
+    /// How long the fingers were on the touchpad
+    pub duration: Duration,
+}
+
+impl GestureEvent {
+    /// The average speed (in device units per second) of the fingers
+    pub fn velocity(&self) -> f64 {
+        let secs = self.duration.as_secs_f64();
+        if secs > 0f64 {
+            self.distance / secs
+        } else {
+            0f64
+        }
+    }
+}
+
+impl Gesture {
+    /// The name of the gesture type, as used in the configuration file.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Gesture::Tap { .. } => "tap",
+            Gesture::Swipe { .. } => "swipe",
+            Gesture::Pinch { .. } => "pinch",
+            Gesture::Rotate { .. } => "rotate",
+            Gesture::Hold { .. } => "hold",
+            Gesture::Slide { .. } => "slide",
+        }
+    }
+
+    pub fn fingers(&self) -> Fingers {
+        match self {
+            Gesture::Tap { fingers, .. }
+            | Gesture::Swipe { fingers, .. }
+            | Gesture::Pinch { fingers, .. }
+            | Gesture::Rotate { fingers, .. }
+            | Gesture::Hold { fingers, .. }
+            | Gesture::Slide { fingers, .. } => *fingers,
+        }
+    }
+
+    /// The direction of the gesture, as used in the configuration file, if it has one.
+    pub fn direction(&self) -> Option<&'static str> {
+        match self {
+            Gesture::Swipe { direction, .. } | Gesture::Slide { direction, .. } => {
+                Some(direction.name())
+            }
+            Gesture::Pinch { direction, .. } => Some(match direction {
+                PinchDirection::In => "in",
+                PinchDirection::Out => "out",
+            }),
+            Gesture::Rotate { direction, .. } => Some(match direction {
+                RotateDirection::Clockwise => "clockwise",
+                RotateDirection::CounterClockwise => "counterclockwise",
+            }),
+            Gesture::Tap { .. } | Gesture::Hold { .. } => None,
+        }
+    }
 }
 
 #[derive(Clone, Debug, Default)]
@@ -144,9 +1293,121 @@ fn get_distance(pos1: &Position, pos2: &Position) -> f64 {
     (((pos2.x - pos1.x).pow(2) + (pos2.y - pos1.y).pow(2)) as f64).sqrt()
 }
 
-fn get_direction(pos1: &Position, pos2: &Position) -> Direction {
+/// Returns the center of a group of positions and the average distance of each from that center
+fn get_spread(positions: &[&Position]) -> (Position, f64) {
+    let count = positions.len() as i32;
+    let center = pos(
+        positions.iter().map(|p| p.x).sum::<i32>() / count,
+        positions.iter().map(|p| p.y).sum::<i32>() / count,
+    );
+    let spread = positions
+        .iter()
+        .map(|p| get_distance(&center, p))
+        .sum::<f64>()
+        / count as f64;
+    (center, spread)
+}
+
+/// Returns the average change in the angle (in radians) of each position around its center. Since
+/// the y-axis points down, a positive value indicates a clockwise rotation.
+fn get_rotation(
+    start: &[&Position],
+    start_center: &Position,
+    end: &[&Position],
+    end_center: &Position,
+) -> f64 {
+    use std::f64::consts::PI;
+
+    let angle =
+        |p: &Position, center: &Position| ((p.y - center.y) as f64).atan2((p.x - center.x) as f64);
+    let total: f64 = start
+        .iter()
+        .zip(end)
+        .map(|(p1, p2)| {
+            let delta = angle(p2, end_center) - angle(p1, start_center);
+            // Take the shorter way around the circle
+            if delta > PI {
+                delta - 2f64 * PI
+            } else if delta < -PI {
+                delta + 2f64 * PI
+            } else {
+                delta
+            }
+        })
+        .sum();
+    total / start.len() as f64
+}
+
+/// Determines whether the tools moved towards or away from one another or around their center, as
+/// opposed to all moving together in the same direction. Returns the gesture along with the
+/// distance travelled by the tools towards/away from or around their center.
+fn get_multitouch_gesture(
+    fingers: Fingers,
+    min_pinch_distance: f64,
+    start_xy: &[&Position],
+    end_xy: &[&Position],
+) -> Option<(Gesture, f64)> {
+    let (start_center, start_spread) = get_spread(start_xy);
+    let (end_center, end_spread) = get_spread(end_xy);
+    let spread_delta = end_spread - start_spread;
+    let rotation = get_rotation(start_xy, &start_center, end_xy, &end_center);
+    // The distance travelled by each finger around the center
+    let arc = rotation.abs() * end_spread;
+    debug!("Spread: {start_spread} -> {end_spread}, rotation: {rotation}");
+
+    // Swiping may also change the spread or angle a little, so the change has to outweigh the
+    // movement of the fingers as a group.
+    let group_travel = get_distance(&start_center, &end_center);
+
+    // The fingers usually drift apart or together a little while rotating and vice versa, so go
+    // with whichever movement was the more pronounced.
+    if rotation.abs() >= MIN_ROTATE_ANGLE.to_radians()
+        && arc > group_travel
+        && arc > spread_delta.abs()
+    {
+        let direction = if rotation > 0f64 {
+            RotateDirection::Clockwise
+        } else {
+            RotateDirection::CounterClockwise
+        };
+        Some((Gesture::Rotate { fingers, direction }, arc))
+    } else if spread_delta.abs() >= min_pinch_distance && spread_delta.abs() > group_travel {
+        let direction = if spread_delta > 0f64 {
+            PinchDirection::Out
+        } else {
+            PinchDirection::In
+        };
+        Some((Gesture::Pinch { fingers, direction }, spread_delta.abs()))
+    } else {
+        None
+    }
+}
+
+/// Returns the direction of travel between two positions. If diagonals are enabled, a movement
+/// within `diagonal_width / 2` degrees of a diagonal is reported as such.
+fn get_direction(pos1: &Position, pos2: &Position, settings: &Settings) -> Direction {
+    if settings.diagonals {
+        let diagonal_width = settings.diagonal_width;
+        // The angle of travel, measured counter-clockwise from the positive x-axis. The y-axis
+        // is inverted so that "up" is at 90 degrees.
+        let angle = f64::from(pos1.y - pos2.y)
+            .atan2(f64::from(pos2.x - pos1.x))
+            .to_degrees();
+        let diagonal = match angle {
+            a if (a - 45f64).abs() <= diagonal_width / 2f64 => Some(Direction::UpRight),
+            a if (a - 135f64).abs() <= diagonal_width / 2f64 => Some(Direction::UpLeft),
+            a if (a + 45f64).abs() <= diagonal_width / 2f64 => Some(Direction::DownRight),
+            a if (a + 135f64).abs() <= diagonal_width / 2f64 => Some(Direction::DownLeft),
+            _ => None,
+        };
+        if let Some(diagonal) = diagonal {
+            return diagonal;
+        }
+    }
+
     // It's much easier to scroll side-to-side than up-down, so include a bias
-    if (pos2.x - pos1.x).abs() > ((1.05f64 * (pos2.y - pos1.y) as f64) as i32).abs() {
+    let bias = settings.direction_bias;
+    if (pos2.x - pos1.x).abs() > ((bias * (pos2.y - pos1.y) as f64) as i32).abs() {
         // Interpret as movement along the x-axis only
         if pos2.x > pos1.x {
             Direction::Right
@@ -170,6 +1431,9 @@ fn get_direction(pos1: &Position, pos2: &Position) -> Direction {
 /// `TouchpadState` tracks the status of all slots.
 #[derive(Debug, Default)]
 struct TouchpadState {
+    pub settings: Settings,
+    pub thresholds: Thresholds,
+    pub dimensions: Option<Dimensions>,
     pub slot_states: Vec<Option<SlotState>>,
     pub start_xy: Option<Position>,
     pub end_xy: Option<Position>,
@@ -181,6 +1445,14 @@ struct TouchpadState {
     pub gesture_end: Option<f64>,
     pub with_btn_tool: bool,
     pub last_slot: Option<usize>,
+    /// Set when a tap with this many fingers may continue a multi-tap sequence, exempting it from
+    /// debouncing.
+    pub awaiting_tap: Option<Fingers>,
+    /// The numbers of fingers for which a swipe with a physical click has been bound, with `None`
+    /// for a binding that matches any number of fingers.
+    pub click_fingers: BTreeSet<Option<Fingers>>,
+    /// Set if the touchpad has been physically clicked during the current gesture.
+    pub clicked: bool,
 }
 
 #[derive(Clone, Debug, Default)]
@@ -213,6 +1485,11 @@ impl SlotState {
         }
     }
 
+    /// The most recently reported position of the tool, if any.
+    pub fn last_position(&self) -> Option<&Position> {
+        self.end_xy.as_ref().or(self.start_xy.as_ref())
+    }
+
     #[allow(unused)]
     pub fn distance(&self) -> Option<f64> {
         if let (Some(start_xy), Some(end_xy)) = (&self.start_xy, &self.end_xy) {
@@ -223,9 +1500,9 @@ impl SlotState {
     }
 
     #[allow(unused)]
-    pub fn direction(&self) -> Option<Direction> {
+    pub fn direction(&self, settings: &Settings) -> Option<Direction> {
         if let (Some(start_xy), Some(end_xy)) = (&self.start_xy, &self.end_xy) {
-            Some(get_direction(start_xy, end_xy))
+            Some(get_direction(start_xy, end_xy, settings))
         } else {
             None
         }
@@ -246,9 +1523,20 @@ impl TouchpadState {
         self.last_finger = None;
         self.gesture_start = None;
         self.gesture_end = None;
+        self.clicked = false;
     }
 
-    fn update(&mut self, report: &mut SynReport) -> Option<Gesture> {
+    fn update(&mut self, report: &mut SynReport) -> Option<GestureEvent> {
+        // Discard whatever state was left over if there hasn't been any input in a while. The
+        // report itself starts the new input, so it's processed as usual after the reset.
+        if let Some(event) = report.events.first() {
+            let timeout = self.settings.event_timeout as f64 / 1000f64;
+            if self.last_ts != 0f64 && event.time - self.last_ts >= timeout {
+                debug!("No events for {:.3}s", event.time - self.last_ts);
+                self.reset();
+            }
+        }
+
         let mut reset = false;
         let mut overall_x = None;
         let mut overall_y = None;
@@ -265,18 +1553,10 @@ impl TouchpadState {
             // Some drivers don't generate a matching ABS_MT_POSITION_X/Y counterpart if the tool
             // hasn't moved on that axis (i.e. only moves in x or in y). Re-use old location in
             // that case.
-            let mut slot_x = (slot.end_xy.as_ref())
-                .or(slot.start_xy.as_ref())
-                .map(|pos| pos.x);
-            let mut slot_y = (slot.end_xy.as_ref())
-                .or(slot.start_xy.as_ref())
-                .map(|pos| pos.y);
+            let mut slot_x = slot.last_position().map(|pos| pos.x);
+            let mut slot_y = slot.last_position().map(|pos| pos.y);
 
             for event in &report.events {
-                if event.time - self.last_ts >= EVENT_TIMEOUT {
-                    reset = true;
-                    break;
-                }
                 self.last_ts = event.time;
 
                 match event.event {
@@ -301,10 +1581,18 @@ impl TouchpadState {
                                 self.slot_states.get_mut(slot_id).unwrap()
                             }
                         };
-                        *s = Some(Default::default());
-                        slot = s.as_mut().unwrap();
+                        // The slot retains its state (and its start position) until a new tool
+                        // is assigned to it via ABS_MT_TRACKING_ID.
+                        slot = s.get_or_insert_with(Default::default);
+                        slot_x = slot.last_position().map(|pos| pos.x);
+                        slot_y = slot.last_position().map(|pos| pos.y);
                         self.last_slot = Some(slot_id);
                     }
+                    EvdevEvent::Abs(EV_ABS::ABS_MT_TRACKING_ID) if event.value != -1 => {
+                        // A new tool has been placed on the touchpad and assigned to this slot
+                        *slot = Default::default();
+                        (slot_x, slot_y) = (None, None);
+                    }
                     EvdevEvent::Abs(EV_ABS::ABS_MT_POSITION_X) => {
                         slot_x = Some(event.value);
                         if let Some(slot_y) = slot_y {
@@ -343,8 +1631,21 @@ impl TouchpadState {
                         self.gesture_start = Some(event.time);
                         self.last_finger.replace(Fingers::Four);
                     }
+                    EvdevEvent::Key(EV_KEY::BTN_TOOL_QUINTTAP) if event.value == 1 => {
+                        debug!("five finger press");
+                        self.with_btn_tool = true;
+                        self.gesture_start = Some(event.time);
+                        self.last_finger.replace(Fingers::Five);
+                    }
 
                     // Physical button press registered ("force touch")
+                    EvdevEvent::Key(EV_KEY::BTN_LEFT | EV_KEY::BTN_RIGHT)
+                        if !self.click_fingers.is_empty() =>
+                    {
+                        // Swipes with a click have been bound, so whether the gesture is discarded
+                        // is decided once the number of fingers is known.
+                        self.clicked |= event.value == 1;
+                    }
                     EvdevEvent::Key(EV_KEY::BTN_LEFT | EV_KEY::BTN_RIGHT) => {
                         // If any gesture ended up pressing hard enough to trigger a physical click
                         // event, discard all the events in the report altogether.
@@ -358,7 +1659,7 @@ impl TouchpadState {
                         slot.complete = true;
                         self.last_slot = None;
 
-                        let max_fingers = self.max_fingers.map(|m| m as u8).unwrap_or(0);
+                        let max_fingers = self.max_fingers.map(Fingers::count).unwrap_or(0);
                         debug!("{} finger remove", max_fingers);
 
                         // We consider the gesture to be over when the first finger is removed.
@@ -381,22 +1682,13 @@ impl TouchpadState {
         // fallback to track tool count.
         // See issue #9 and https://www.kernel.org/doc/Documentation/input/event-codes.txt
         if !report.events.is_empty() && !self.with_btn_tool {
-            let active_tools = self
-                .slot_states
-                .iter()
-                .filter(|s| s.as_ref().map(SlotState::is_active).unwrap_or(false))
-                .count();
+            let active_tools = self.active_tools();
             let event_time = report.events.last().unwrap().time;
-            let max_finger_count = self.max_fingers.map(|f| f as usize).unwrap_or(0);
+            let max_finger_count = self.max_fingers.map(Fingers::count).unwrap_or(0);
             if active_tools > max_finger_count {
                 debug!("{} finger press (calculated)", active_tools);
                 self.gesture_start = Some(event_time);
-                self.last_finger = Some(match active_tools {
-                    1 => Fingers::One,
-                    2 => Fingers::Two,
-                    3 => Fingers::Three,
-                    _ => Fingers::Four,
-                });
+                self.last_finger = Fingers::from_count(active_tools);
             } else if active_tools < max_finger_count && self.last_finger.is_some() {
                 debug!("{} finger remove (calculated)", max_finger_count);
                 self.last_finger = None;
@@ -404,6 +1696,15 @@ impl TouchpadState {
             }
         }
 
+        // There's no BTN_TOOL_* code for more than five fingers, so count the tools in use instead.
+        if self.last_finger == Some(Fingers::Five) {
+            let active_tools = self.active_tools();
+            if active_tools > 5 {
+                debug!("{} finger press (calculated)", active_tools);
+                self.last_finger = Fingers::from_count(active_tools);
+            }
+        }
+
         if reset {
             self.reset();
             return None;
@@ -441,9 +1742,9 @@ impl TouchpadState {
 
         debug!("Remaining fingers: {:?}", self.last_finger);
         if self.last_finger.is_none() {
-            if let Some(gesture) = self.process() {
+            if let Some(event) = self.process() {
                 self.reset();
-                return Some(gesture);
+                return Some(event);
             }
         }
 
@@ -458,7 +1759,43 @@ impl TouchpadState {
         }
     }
 
-    fn process(&mut self) -> Option<Gesture> {
+    /// The distance travelled by the fingers since the start of the gesture.
+    fn distance(&self) -> f64 {
+        match (&self.start_xy, &self.end_xy) {
+            (Some(start_xy), Some(end_xy)) => get_distance(start_xy, end_xy),
+            _ => 0f64,
+        }
+    }
+
+    /// How long the fingers have been (or were) on the touchpad.
+    fn duration(&self) -> Duration {
+        let start = self.gesture_start.unwrap_or(self.last_ts);
+        let end = self.gesture_end.unwrap_or(self.last_ts);
+        Duration::from_secs_f64((end - start).max(0f64))
+    }
+
+    /// Classifies the speed of a swipe that travelled `distance` in `duration`.
+    fn speed(&self, distance: f64, duration: Duration) -> Option<Speed> {
+        let secs = duration.as_secs_f64();
+        if secs <= 0f64 {
+            return None;
+        }
+        match distance / secs {
+            velocity if velocity >= self.thresholds.fast_swipe_speed => Some(Speed::Fast),
+            velocity if velocity <= self.thresholds.slow_swipe_speed => Some(Speed::Slow),
+            _ => None,
+        }
+    }
+
+    /// The number of tools currently on the touchpad, according to the multitouch slots.
+    fn active_tools(&self) -> usize {
+        self.slot_states
+            .iter()
+            .filter(|s| s.as_ref().map(SlotState::is_active).unwrap_or(false))
+            .count()
+    }
+
+    fn process(&mut self) -> Option<GestureEvent> {
         if self.start_xy.is_none() {
             debug!("Received report but indeterminate start");
             return None;
@@ -474,33 +1811,292 @@ impl TouchpadState {
             }
         };
 
-        let distance = match &self.end_xy {
-            Some(end_xy) => get_distance(self.start_xy.as_ref().unwrap(), end_xy),
-            None => 0f64,
-        };
-
+        let distance = self.distance();
         debug!("Distance: {distance}");
 
+        if self.clicked {
+            let bound =
+                self.click_fingers.contains(&Some(fingers)) || self.click_fingers.contains(&None);
+            // A click without moving is just a click
+            if !bound || distance < self.thresholds.min_swipe_distance {
+                debug!("disregarding gesture that included a physical button press");
+                self.reset();
+                return None;
+            }
+        }
+
+        // Gestures that depend on how the fingers moved relative to one another
+        let multitouch = match self.slot_positions() {
+            Some((start_xy, end_xy)) if fingers != Fingers::One && !self.clicked => {
+                get_multitouch_gesture(
+                    fingers,
+                    self.thresholds.min_pinch_distance,
+                    &start_xy,
+                    &end_xy,
+                )
+            }
+            _ => None,
+        };
+
         trace!("self.last_ts: {}", self.last_ts);
         trace!("self.last_gesture_time: {}", self.last_gesture_time);
-        if self.last_ts - self.last_gesture_time > DEBOUNCE_TIME {
+        let in_tap_sequence = self.awaiting_tap == Some(fingers)
+            && multitouch.is_none()
+            && distance < self.thresholds.min_swipe_distance;
+        let debounce_time = self.settings.debounce_time as f64 / 1000f64;
+        if in_tap_sequence || self.last_ts - self.last_gesture_time > debounce_time {
             self.last_gesture_time = self.last_ts;
-            if distance < MIN_SWIPE_DISTANCE {
+            let duration = self.duration();
+            let (gesture, distance) = if let Some((gesture, distance)) = multitouch {
+                debug!("{gesture:?} detected");
+                (gesture, distance)
+            } else if distance < self.thresholds.min_swipe_distance {
                 debug!("tap detected");
-                Some(Gesture::Tap { fingers })
+                (Gesture::Tap { fingers, count: 1 }, distance)
             } else {
                 debug!("gesture detected");
-                Some(Gesture::Swipe {
+                let direction = get_direction(
+                    self.start_xy.as_ref().unwrap(),
+                    self.end_xy.as_ref().unwrap(),
+                    &self.settings,
+                );
+                let gesture = Gesture::Swipe {
                     fingers,
-                    direction: get_direction(
-                        self.start_xy.as_ref().unwrap(),
-                        self.end_xy.as_ref().unwrap(),
-                    ),
-                })
-            }
+                    edge: self.start_edge(&direction),
+                    direction,
+                    click: self.clicked,
+                    speed: self.speed(distance, duration),
+                };
+                (gesture, distance)
+            };
+            Some(GestureEvent {
+                gesture,
+                distance,
+                duration,
+            })
         } else {
             debug!("Gesture ignored by debounce");
             None
         }
     }
+
+    /// Returns the edge of the touchpad a gesture moving in `direction` started from, if it started
+    /// close enough to the edge it is moving away from.
+    fn start_edge(&self, direction: &Direction) -> Option<Edge> {
+        let Dimensions { x, y } = self.dimensions.as_ref()?;
+        let start_xy = self.start_xy.as_ref()?;
+        let Thresholds { edge_x, edge_y, .. } = self.thresholds;
+
+        match direction {
+            Direction::Right if f64::from(start_xy.x - x.min) <= edge_x => Some(Edge::Left),
+            Direction::Left if f64::from(x.max - start_xy.x) <= edge_x => Some(Edge::Right),
+            Direction::Down if f64::from(start_xy.y - y.min) <= edge_y => Some(Edge::Top),
+            Direction::Up if f64::from(y.max - start_xy.y) <= edge_y => Some(Edge::Bottom),
+            _ => None,
+        }
+    }
+
+    /// Returns the first and last known positions of each tool tracked during the gesture.
+    fn slot_positions(&self) -> Option<(Vec<&Position>, Vec<&Position>)> {
+        let slots: Vec<&SlotState> = self
+            .slot_states
+            .iter()
+            .flatten()
+            .filter(|s| s.has_pos())
+            .collect();
+        if slots.len() < 2 {
+            return None;
+        }
+
+        let start_xy = slots.iter().map(|s| s.start_xy.as_ref().unwrap()).collect();
+        let end_xy = slots.iter().map(|s| s.last_position().unwrap()).collect();
+        Some((start_xy, end_xy))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feeds `event_loop` the reports of fingers moving in straight lines from their start to
+    /// their end positions over 100ms starting at `second`, before being lifted, returning the
+    /// gestures recognized along the way.
+    fn perform(
+        event_loop: &mut EventLoop,
+        second: i64,
+        fingers: &[(Position, Position)],
+    ) -> Vec<Gesture> {
+        const FRAMES: i32 = 10;
+        let tool = [
+            EV_KEY::BTN_TOOL_FINGER,
+            EV_KEY::BTN_TOOL_DOUBLETAP,
+            EV_KEY::BTN_TOOL_TRIPLETAP,
+            EV_KEY::BTN_TOOL_QUADTAP,
+            EV_KEY::BTN_TOOL_QUINTTAP,
+        ][fingers.len() - 1];
+
+        let mut reports = Vec::new();
+        for frame in 0..=FRAMES {
+            let mut report = Vec::new();
+            for (slot, (start, end)) in fingers.iter().enumerate() {
+                report.push((EV_ABS::ABS_MT_SLOT, slot as i32));
+                if frame == 0 {
+                    report.push((EV_ABS::ABS_MT_TRACKING_ID, slot as i32));
+                }
+                let x = start.x + (end.x - start.x) * frame / FRAMES;
+                let y = start.y + (end.y - start.y) * frame / FRAMES;
+                report.push((EV_ABS::ABS_MT_POSITION_X, x));
+                report.push((EV_ABS::ABS_MT_POSITION_Y, y));
+                if slot == 0 {
+                    report.push((EV_ABS::ABS_X, x));
+                    report.push((EV_ABS::ABS_Y, y));
+                }
+            }
+            let mut report: Vec<_> = report
+                .into_iter()
+                .map(|(code, value)| (EventCode::EV_ABS(code), value))
+                .collect();
+            if frame == 0 {
+                report.push((EventCode::EV_KEY(tool), 1));
+            }
+            reports.push(report);
+        }
+        let mut lift = Vec::new();
+        for slot in 0..fingers.len() {
+            lift.push((EventCode::EV_ABS(EV_ABS::ABS_MT_SLOT), slot as i32));
+            lift.push((EventCode::EV_ABS(EV_ABS::ABS_MT_TRACKING_ID), -1));
+        }
+        lift.push((EventCode::EV_KEY(tool), 0));
+        reports.push(lift);
+
+        let mut gestures = Vec::new();
+        for (i, report) in reports.into_iter().enumerate() {
+            let time = TimeVal::new(second, i as i64 * 10_000);
+            for (code, value) in report {
+                gestures.extend(event_loop.add_event(time, code, value));
+            }
+            let syn = EventCode::EV_SYN(EV_SYN::SYN_REPORT);
+            gestures.extend(event_loop.add_event(time, syn, 0));
+        }
+        gestures.into_iter().map(|event| event.gesture).collect()
+    }
+
+    #[test]
+    fn slides_only_replace_swipes_in_their_direction() {
+        let mut event_loop = EventLoop::new(Settings::default(), None);
+        event_loop.register(&GesturePattern::Slide {
+            fingers: Some(Fingers::Three),
+            direction: Some(Direction::Right),
+            phase: Phase::Update,
+            step: None,
+        });
+        let three = |dx: i32| {
+            [
+                (pos(1000, 1000), pos(1000 + dx, 1000)),
+                (pos(1500, 1000), pos(1500 + dx, 1000)),
+                (pos(2000, 1000), pos(2000 + dx, 1000)),
+            ]
+        };
+
+        let left = perform(&mut event_loop, 1, &three(-1000));
+        assert_eq!(left.len(), 1, "{left:?}");
+        assert!(
+            matches!(
+                left[0],
+                Gesture::Swipe {
+                    fingers: Fingers::Three,
+                    direction: Direction::Left,
+                    ..
+                }
+            ),
+            "{:?}",
+            left
+        );
+
+        let right = perform(&mut event_loop, 2, &three(1000));
+        assert!(!right.is_empty(), "{:?}", right);
+        assert!(
+            right.iter().all(|gesture| matches!(
+                gesture,
+                Gesture::Slide {
+                    fingers: Fingers::Three,
+                    ..
+                }
+            )),
+            "{:?}",
+            right
+        );
+    }
+
+    #[test]
+    fn parse_lengths() {
+        for (s, expected) in [
+            ("300", Some(Length::Units(300f64))),
+            ("12.5", Some(Length::Units(12.5))),
+            ("0", Some(Length::Units(0f64))),
+            ("5mm", Some(Length::Millimeters(5f64))),
+            (" 5 mm ", Some(Length::Millimeters(5f64))),
+            ("2.5mm", Some(Length::Millimeters(2.5))),
+            ("10%", Some(Length::Percent(10f64))),
+            ("10 %", Some(Length::Percent(10f64))),
+            ("", None),
+            ("mm", None),
+            ("%", None),
+            ("-5", None),
+            ("-5mm", None),
+            ("5cm", None),
+            ("5 in", None),
+            ("10%%", None),
+            ("five", None),
+        ] {
+            assert_eq!(s.parse::<Length>().ok(), expected, "{s:?}");
+        }
+    }
+
+    #[test]
+    fn directions() {
+        let four = Settings::default();
+        let eight = Settings {
+            diagonals: true,
+            ..Default::default()
+        };
+        let wide = Settings {
+            diagonals: true,
+            diagonal_width: 80f64,
+            ..Default::default()
+        };
+        for ((x, y), settings, expected) in [
+            ((100, 0), &four, Direction::Right),
+            ((-100, 0), &four, Direction::Left),
+            ((0, -100), &four, Direction::Up),
+            ((0, 100), &four, Direction::Down),
+            // Exactly diagonal movement is biased towards up-down
+            ((100, -100), &four, Direction::Up),
+            ((-100, 100), &four, Direction::Down),
+            ((100, -90), &four, Direction::Right),
+            ((100, 0), &eight, Direction::Right),
+            ((-100, 0), &eight, Direction::Left),
+            ((0, -100), &eight, Direction::Up),
+            ((0, 100), &eight, Direction::Down),
+            ((100, -100), &eight, Direction::UpRight),
+            ((-100, -100), &eight, Direction::UpLeft),
+            ((100, 100), &eight, Direction::DownRight),
+            ((-100, 100), &eight, Direction::DownLeft),
+            // 30 degrees is within 22.5 degrees of the diagonal, 20 degrees isn't
+            ((100, -58), &eight, Direction::UpRight),
+            ((-100, 58), &eight, Direction::DownLeft),
+            ((100, -36), &eight, Direction::Right),
+            ((36, 100), &eight, Direction::Down),
+            ((100, -36), &wide, Direction::UpRight),
+            ((36, 100), &wide, Direction::DownRight),
+        ] {
+            assert_eq!(
+                get_direction(&pos(1000, 1000), &pos(1000 + x, 1000 + y), settings),
+                expected,
+                "({x}, {y}), diagonals: {}, width: {}",
+                settings.diagonals,
+                settings.diagonal_width
+            );
+        }
+    }
 }
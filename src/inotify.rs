@@ -0,0 +1,82 @@
+//! A minimal inotify(7) wrapper, used to notice device nodes being added so their watchers can be
+//! (re)attached.
+
+use crate::epoll::syscall;
+use std::ffi::{CString, OsStr, OsString};
+use std::io::{ErrorKind, Result};
+use std::os::unix::prelude::*;
+use std::path::Path;
+
+pub(crate) struct Inotify {
+    fd: OwnedFd,
+}
+
+impl Inotify {
+    pub fn new() -> Result<Inotify> {
+        let fd = syscall!(inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC))?;
+        Ok(Inotify {
+            fd: unsafe { OwnedFd::from_raw_fd(fd) },
+        })
+    }
+
+    /// Watch `dir` for entries being created, moved in, or having their attributes changed (which
+    /// is how we find out that udev has finished setting the permissions of a new device node).
+    /// Adding a watch for a directory that's already being watched is a no-op.
+    pub fn watch_dir(&self, dir: &Path) -> Result<()> {
+        let path = CString::new(dir.as_os_str().as_bytes())?;
+        syscall!(inotify_add_watch(
+            self.fd.as_raw_fd(),
+            path.as_ptr(),
+            libc::IN_CREATE | libc::IN_MOVED_TO | libc::IN_ATTRIB | libc::IN_ONLYDIR,
+        ))?;
+        Ok(())
+    }
+
+    /// Read all pending events, returning the names of the directory entries they were about, or
+    /// `None` if the kernel's queue overflowed and some events were lost.
+    pub fn read_names(&self) -> Result<Option<Vec<OsString>>> {
+        const HEADER: usize = std::mem::size_of::<libc::inotify_event>();
+        let mut names = Vec::new();
+        let mut overflowed = false;
+        // Large enough for at least one event with the longest possible name
+        let mut buffer = [0u8; 4096];
+        loop {
+            let len = match syscall!(read(
+                self.fd.as_raw_fd(),
+                buffer.as_mut_ptr() as *mut libc::c_void,
+                buffer.len(),
+            )) {
+                Ok(len) => len as usize,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+
+            let mut offset = 0;
+            while offset + HEADER <= len {
+                let event: libc::inotify_event = unsafe {
+                    std::ptr::read_unaligned(buffer[offset..].as_ptr() as *const libc::inotify_event)
+                };
+                let start = offset + HEADER;
+                offset = start + event.len as usize;
+                if event.mask & libc::IN_Q_OVERFLOW != 0 {
+                    overflowed = true;
+                    continue;
+                }
+                // The name is padded with NULs
+                let name = &buffer[start..offset.min(len)];
+                let name = &name[..name.iter().position(|&b| b == 0).unwrap_or(name.len())];
+                if !name.is_empty() {
+                    names.push(OsStr::from_bytes(name).to_owned());
+                }
+            }
+        }
+        Ok(if overflowed { None } else { Some(names) })
+    }
+}
+
+impl AsRawFd for Inotify {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+}
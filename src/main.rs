@@ -1,21 +1,43 @@
 mod config;
+mod device;
 mod epoll;
 #[cfg(not(feature = "logging"))]
 mod errorlog;
 mod events;
+mod inotify;
+mod mode;
+mod uinput;
 
-use config::Action;
+use config::{Action, Device, DeviceConfig};
 use epoll::Epoll;
 use evdev_rs::Device as EvDevice;
-use events::{EventLoop, Gesture};
+use events::{Dimensions, EventLoop, GestureEvent};
+use inotify::Inotify;
 #[allow(unused)]
 use log::{debug, error, info, trace, warn};
+use std::collections::{BTreeMap, BTreeSet};
+use std::ffi::OsStr;
 use std::io::ErrorKind;
-use std::os::fd::AsRawFd;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::path::{Path, PathBuf};
 use std::process::Command;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+use std::thread::{Scope, ScopedJoinHandle};
+use std::time::{Duration, Instant};
 
 static SIGHUP: AtomicBool = AtomicBool::new(false);
+/// Set by `--watch` to print gestures and their bindings instead of running any actions.
+static WATCH: AtomicBool = AtomicBool::new(false);
+/// The write end of a pipe used to wake the main thread, e.g. on SIGHUP or when a device is removed.
+static WAKE_FD: AtomicI32 = AtomicI32::new(-1);
+
+/// Directories watched for device nodes being added, in addition to the parent directory of each
+/// configured device.
+const DEVICE_DIRS: &[&str] = &["/dev/input", "/dev/input/by-id", "/dev/input/by-path"];
+/// How long to wait before attaching a device again after an error, doubled with each consecutive
+/// failure up to `MAX_RETRY_DELAY`.
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
 
 fn print_version<W: std::io::Write>(target: &mut W) {
     let _ = writeln!(
@@ -41,6 +63,13 @@ fn print_help<W: std::io::Write>(target: &mut W) {
         "Options:",
         "  -h --help     Print this help message",
         "  -V --version  Print version info",
+        "  -l --list-devices",
+        "                List input devices, marking those that support gestures",
+        "  -w --watch [DEVICE...]",
+        "                Print each recognized gesture and the binding it matches,",
+        "                without running any actions, for the given or configured devices",
+        "  -m --mode NAME",
+        "                Switch running syngestures instances to the named mode",
         "",
         "A valid syngestures config file must be installed to one of the",
         "following locations before executing syngestures:",
@@ -82,14 +111,37 @@ fn init_logger() {
 
 extern "C" fn on_sighup(_: libc::c_int) {
     SIGHUP.store(true, Ordering::Relaxed);
+    wake();
+}
+
+/// Wake the main thread so it checks for SIGHUP and tries to attach any missing devices. This is
+/// async-signal-safe.
+fn wake() {
+    let fd = WAKE_FD.load(Ordering::Relaxed);
+    if fd >= 0 {
+        unsafe {
+            libc::write(fd, [1u8].as_ptr() as *const libc::c_void, 1);
+        }
+    }
+}
+
+/// Create the pipe written to by [`wake()`], returning its read end.
+fn wake_pipe() -> std::io::Result<OwnedFd> {
+    let mut fds = [-1; 2];
+    if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_NONBLOCK | libc::O_CLOEXEC) } == -1 {
+        return Err(std::io::Error::last_os_error());
+    }
+    WAKE_FD.store(fds[1], Ordering::Relaxed);
+    Ok(unsafe { OwnedFd::from_raw_fd(fds[0]) })
 }
 
 fn main() {
     init_logger();
 
-    let args = std::env::args();
-    #[allow(clippy::never_loop)]
-    for arg in args.skip(1) {
+    let mut args = std::env::args().skip(1);
+    // The devices given after `--watch`, if any
+    let mut watch_paths = Vec::new();
+    while let Some(arg) = args.next() {
         match arg.as_str() {
             "-h" | "--help" => {
                 print_help(&mut std::io::stdout());
@@ -99,6 +151,36 @@ fn main() {
                 print_version(&mut std::io::stdout());
                 std::process::exit(0);
             }
+            "-l" | "--list-devices" => {
+                device::print_devices(&mut std::io::stdout());
+                std::process::exit(0);
+            }
+            "-w" | "--watch" | "--debug-gestures" => {
+                WATCH.store(true, Ordering::Relaxed);
+            }
+            path if WATCH.load(Ordering::Relaxed) && !path.starts_with('-') => {
+                watch_paths.push(path.to_owned());
+            }
+            "-m" | "--mode" => {
+                let name = match args.next() {
+                    Some(name) => name,
+                    None => {
+                        eprintln!("{arg}: Missing mode name!");
+                        std::process::exit(-1);
+                    }
+                };
+                match mode::request(&name) {
+                    Ok(0) => {
+                        eprintln!("No running syngestures instance found");
+                        std::process::exit(-1);
+                    }
+                    Ok(_) => std::process::exit(0),
+                    Err(e) => {
+                        eprintln!("Error switching to mode {name}: {e}");
+                        std::process::exit(-1);
+                    }
+                }
+            }
             _ => {
                 eprintln!("{arg}: Invalid option!");
                 eprintln!("Try 'syngestures --help' for more info");
@@ -109,7 +191,7 @@ fn main() {
 
     // Install a SIGHUP handler to tell us to reload the configuration file
     unsafe {
-        let result = libc::signal(libc::SIGHUP, on_sighup as libc::sighandler_t);
+        let result = libc::signal(libc::SIGHUP, on_sighup as *const () as libc::sighandler_t);
         if result != 0 {
             match std::io::Error::last_os_error().raw_os_error() {
                 Some(errno) if errno == libc::ENOTTY => {
@@ -121,6 +203,8 @@ fn main() {
         }
     }
 
+    mode::install_signal_handler();
+
     // Tell the kernel to reap child processes automatically and not require a wait(2) call.
     // Note that this probably completely breaks waiting on child processes to complete!
     unsafe {
@@ -130,15 +214,38 @@ fn main() {
         assert_eq!(result, 0);
     }
 
+    let wake_fd = wake_pipe().expect("Unable to create pipe");
+
     loop {
         let config = config::load();
-        if config.devices.is_empty() {
-            error!("No configured devices");
-            std::process::exit(-1);
+        let no_bindings = DeviceConfig::default();
+        let devices: Vec<(Device, &DeviceConfig)> = if watch_paths.is_empty() {
+            if config.devices.is_empty() {
+                error!("No configured devices");
+                std::process::exit(-1);
+            }
+            config
+                .devices
+                .iter()
+                .map(|(device, device_config)| (device.clone(), device_config))
+                .collect()
+        } else {
+            watch_paths
+                .iter()
+                .map(|path| {
+                    let device_config = config.device_config(path).unwrap_or(&no_bindings);
+                    (Device::Path(path.clone()), device_config)
+                })
+                .collect()
+        };
+
+        // Create the virtual keyboard/mouse ahead of time so it's ready by the first gesture.
+        if config.uses_uinput() && !WATCH.load(Ordering::Relaxed) {
+            uinput::init();
         }
 
         std::thread::scope(|scope| {
-            watch_devices(scope, config);
+            watch_devices(scope, &devices, wake_fd.as_raw_fd());
 
             // We hang here until all device watcher threads have terminated.
             // That's OK for now, but in case of SIGHUP the worker threads won't notice the signal
@@ -155,98 +262,573 @@ fn main() {
     }
 }
 
-fn watch_devices<'scope>(
-    scope: &'scope std::thread::Scope<'scope, '_>,
-    config: config::Configuration,
+fn watch_devices<'scope, 'env>(
+    scope: &'scope Scope<'scope, 'env>,
+    devices: &'env [(Device, &'env DeviceConfig)],
+    wake_fd: RawFd,
 ) {
-    for (device_path, gestures) in config.devices {
-        let device = match EvDevice::new_from_path(&device_path) {
-            Ok(device) => device,
-            Err(e) => {
-                error!("{device_path}: {e}");
-                continue;
+    let mut epoll = Epoll::new().unwrap();
+    let wake_token = epoll.register_read(wake_fd, false).unwrap();
+
+    // Devices that aren't connected yet (or are disconnected later) are attached when their
+    // device node appears.
+    let mut dirs: BTreeSet<&Path> = DEVICE_DIRS.iter().map(Path::new).collect();
+    for (device, _) in devices {
+        if let Device::Path(path) = device {
+            dirs.extend(Path::new(path).parent());
+        }
+    }
+    let inotify = match Inotify::new() {
+        Ok(inotify) => Some(inotify),
+        Err(e) => {
+            warn!("Unable to watch for devices being connected: {e}");
+            None
+        }
+    };
+    let inotify_token = inotify
+        .as_ref()
+        .map(|inotify| epoll.register_read(inotify.as_raw_fd(), false).unwrap());
+    // The names of the watched directories and configured device nodes themselves, so they're
+    // noticed when created (again)
+    let names: BTreeSet<&OsStr> = dirs
+        .iter()
+        .copied()
+        .chain(devices.iter().filter_map(|(device, _)| match device {
+            Device::Path(path) => Some(Path::new(path)),
+            Device::Match(_) => None,
+        }))
+        .filter_map(Path::file_name)
+        .collect();
+
+    let mut workers = Workers::default();
+    let mut first_attempt = true;
+    loop {
+        if SIGHUP.load(Ordering::Relaxed) {
+            return;
+        }
+
+        if let Some(inotify) = &inotify {
+            // Watch any directories created since the last pass, such as /dev/input/by-id when
+            // the first device with an id is connected.
+            for dir in &dirs {
+                if let Err(e) = inotify.watch_dir(dir) {
+                    if e.kind() != ErrorKind::NotFound {
+                        warn!("Unable to watch {}: {e}", dir.display());
+                    }
+                }
             }
-        };
-        let device_fd = device.file().as_raw_fd();
-        scope.spawn(move || {
-            use evdev_rs::enums::*;
-            use evdev_rs::{InputEvent, ReadFlag, ReadStatus};
-
-            let mut epoll = Epoll::new().unwrap();
-            epoll.register_read(device_fd, false).unwrap();
-
-            let mut event_loop = EventLoop::new();
-            let mut read_flag = ReadFlag::NORMAL;
-            'device: loop {
-                if SIGHUP.load(Ordering::Relaxed) {
-                    debug!("Threading exiting because SIGHUP was set.");
-                    return;
+        }
+
+        workers.reap();
+        // Only enumerate the available devices (once per pass) if they need to be matched
+        let mut available = None;
+        for (device, device_config) in devices {
+            match device {
+                Device::Path(device_path) => {
+                    attach_device(
+                        scope,
+                        &mut workers,
+                        device_path,
+                        device_config,
+                        first_attempt,
+                    );
                 }
-                // Work around evdev-rs bug (?) removing `Copy` and `Clone` from `ReadFlag`
-                // https://github.com/ndesh26/evdev-rs/issues/116
-                let read_flag_clone = ReadFlag::from_bits(read_flag.bits()).unwrap();
-                let event = match device.next_event(read_flag_clone) {
-                    Ok((ReadStatus::Success, event)) => event,
-                    Ok((
-                        ReadStatus::Sync,
-                        InputEvent {
-                            event_code: EventCode::EV_SYN(EV_SYN::SYN_DROPPED),
-                            ..
-                        },
-                    )) => {
-                        read_flag = ReadFlag::SYNC;
-                        continue;
+                Device::Match(selector) => {
+                    // Don't bother opening the nodes that are already being watched
+                    let available = available
+                        .get_or_insert_with(|| device::enumerate(|node| !workers.is_busy(node)));
+                    let mut found = false;
+                    for info in available.iter().filter(|info| selector.matches(info)) {
+                        found = true;
+                        attach_device(
+                            scope,
+                            &mut workers,
+                            &info.path,
+                            device_config,
+                            first_attempt,
+                        );
                     }
-                    Ok((ReadStatus::Sync, event)) => event,
-                    Err(e) if e.kind() == ErrorKind::WouldBlock => {
-                        read_flag = ReadFlag::NORMAL;
-                        loop {
-                            match epoll.wait(None) {
-                                Ok(()) => continue 'device,
-                                Err(e) => {
-                                    if e.kind() == ErrorKind::Interrupted {
-                                        continue;
-                                    }
-                                    error!("epoll_wait: {e}");
-                                    break 'device;
-                                }
+                    if !found && first_attempt {
+                        warn!("No {device} found, waiting for one to be connected");
+                    }
+                }
+            }
+        }
+        first_attempt = false;
+
+        if inotify.is_none() && workers.running.is_empty() && workers.next_retry().is_none() {
+            // There's nothing left that could wake us.
+            return;
+        }
+
+        // Wait until a device may have been connected, a worker has exited, a failed device is due
+        // to be retried, or we're reloading.
+        loop {
+            match epoll.wait(workers.next_retry()) {
+                Ok(()) => {}
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    error!("epoll_wait: {e}");
+                    return;
+                }
+            }
+            let woken = epoll.test_read(&wake_token);
+            if woken {
+                let mut buffer = [0u8; 64];
+                while unsafe {
+                    libc::read(
+                        wake_fd,
+                        buffer.as_mut_ptr() as *mut libc::c_void,
+                        buffer.len(),
+                    )
+                } > 0
+                {}
+            }
+            let mut notified = false;
+            let mut connected = false;
+            if let (Some(inotify), Some(token)) = (&inotify, &inotify_token) {
+                notified = epoll.test_read(token);
+                if notified {
+                    // Ignore changes to anything but device nodes, such as udev updating the
+                    // permissions of some unrelated device.
+                    connected = match inotify.read_names() {
+                        Ok(Some(changed)) => changed
+                            .iter()
+                            .any(|name| names.contains(name.as_os_str()) || is_event_node(name)),
+                        Ok(None) => true,
+                        Err(e) => {
+                            error!("inotify: {e}");
+                            true
+                        }
+                    };
+                }
+            }
+            let timed_out = !woken && !notified;
+            if woken || connected || timed_out {
+                break;
+            }
+        }
+    }
+}
+
+/// Whether `name` is that of an evdev node (`event*`) or of a link to one under `/dev/input/by-id`
+/// or `/dev/input/by-path` (e.g. `usb-Apple_Inc._Magic_Trackpad-event-mouse`).
+fn is_event_node(name: &OsStr) -> bool {
+    let name = name.to_string_lossy();
+    name.starts_with("event") || name.contains("-event")
+}
+
+/// The device watcher threads, by the canonical path of the device they're watching.
+#[derive(Default)]
+struct Workers<'scope> {
+    /// The threads along with when they were started
+    running: BTreeMap<PathBuf, (ScopedJoinHandle<'scope, std::io::Result<()>>, Instant)>,
+    /// When the devices whose threads failed may be attached again, along with the number of
+    /// consecutive failures
+    backoff: BTreeMap<PathBuf, (Instant, u32)>,
+}
+
+impl Workers<'_> {
+    /// Clean up after the threads that have exited. Devices are retried after a delay if their
+    /// thread failed, doubling with each consecutive failure so a persistent error doesn't spin.
+    fn reap(&mut self) {
+        let finished: Vec<PathBuf> = self
+            .running
+            .iter()
+            .filter(|(_, (worker, _))| worker.is_finished())
+            .map(|(path, _)| path.clone())
+            .collect();
+        for path in finished {
+            let (worker, started) = self.running.remove(&path).unwrap();
+            if let Ok(Ok(())) = worker.join() {
+                self.backoff.remove(&path);
+                continue;
+            }
+            let failures = match self.backoff.get(&path) {
+                Some((_, failures)) if started.elapsed() < MAX_RETRY_DELAY => failures + 1,
+                _ => 1,
+            };
+            let delay = (RETRY_DELAY * 2u32.saturating_pow(failures - 1)).min(MAX_RETRY_DELAY);
+            info!("{}: Retrying in {}s", path.display(), delay.as_secs());
+            self.backoff
+                .insert(path, (Instant::now() + delay, failures));
+        }
+    }
+
+    /// Whether the device is already being watched or shouldn't be retried just yet.
+    fn is_busy(&self, canonical_path: &Path) -> bool {
+        self.running.contains_key(canonical_path)
+            || matches!(self.backoff.get(canonical_path), Some((retry, _)) if *retry > Instant::now())
+    }
+
+    /// How long until the next device that failed may be retried, if any.
+    fn next_retry(&self) -> Option<Duration> {
+        let now = Instant::now();
+        self.backoff
+            .values()
+            .map(|(retry, _)| *retry)
+            .filter(|retry| *retry > now)
+            .min()
+            .map(|retry| retry - now)
+    }
+}
+
+/// Open the device at `device_path` and start watching it for gestures, unless it's already being
+/// watched. Errors are only logged prominently on the first attempt, as later attempts are expected
+/// to fail until the device is connected (and udev has given us permission to read it).
+fn attach_device<'scope, 'env>(
+    scope: &'scope Scope<'scope, 'env>,
+    workers: &mut Workers<'scope>,
+    device_path: &str,
+    device_config: &'env DeviceConfig,
+    first_attempt: bool,
+) {
+    // The same device may be configured through different links or selectors
+    if let Ok(canonical_path) = std::fs::canonicalize(device_path) {
+        if workers.is_busy(&canonical_path) {
+            return;
+        }
+    }
+    let device = match device::open(device_path) {
+        Ok(device) => device,
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            if first_attempt {
+                warn!("{device_path}: Device not found, waiting for it to be connected");
+            }
+            return;
+        }
+        Err(e) => {
+            if first_attempt {
+                error!("{device_path}: {e}");
+            } else {
+                debug!("{device_path}: {e}");
+            }
+            return;
+        }
+    };
+    let canonical_path = match std::fs::canonicalize(device_path) {
+        Ok(path) => path,
+        Err(e) => {
+            error!("{device_path}: {e}");
+            return;
+        }
+    };
+    if !first_attempt {
+        info!("{device_path}: Device connected");
+    }
+
+    let device_path = device_path.to_owned();
+    let worker = scope.spawn(move || {
+        let result = watch_device(&device_path, device, device_config);
+        // Let the main thread know so it can attach the device again, in case the device node was
+        // already replaced or the error was only temporary.
+        wake();
+        result
+    });
+    workers
+        .running
+        .insert(canonical_path, (worker, Instant::now()));
+}
+
+/// Watch `device` for gestures until it's disconnected (or we're reloading), returning any other
+/// error that stopped us.
+fn watch_device(
+    device_path: &str,
+    device: EvDevice,
+    device_config: &DeviceConfig,
+) -> std::io::Result<()> {
+    use evdev_rs::enums::*;
+    use evdev_rs::{InputEvent, ReadFlag, ReadStatus};
+
+    let device_fd = device.file().as_raw_fd();
+    let dimensions = Dimensions::from_device(&device);
+    if dimensions.is_none() {
+        warn!("{device_path}: Unable to determine touchpad dimensions");
+    }
+
+    let mut epoll = Epoll::new()?;
+    epoll.register_read(device_fd, false)?;
+
+    let mut event_loop = EventLoop::new(device_config.settings.clone(), dimensions.clone());
+    let no_gestures = config::GestureMap::new();
+    let mut mode_generation = None;
+    let mut gestures = &no_gestures;
+    let mut read_flag = ReadFlag::NORMAL;
+    'device: loop {
+        if SIGHUP.load(Ordering::Relaxed) {
+            debug!("Threading exiting because SIGHUP was set.");
+            return Ok(());
+        }
+        let generation = mode::generation();
+        if mode_generation != Some(generation) {
+            mode_generation = Some(generation);
+            gestures = device_config
+                .gestures(&mode::current())
+                .unwrap_or(&no_gestures);
+            event_loop.unregister_all();
+            for gesture in gestures.keys() {
+                event_loop.register(gesture);
+            }
+        }
+        // Work around evdev-rs bug (?) removing `Copy` and `Clone` from `ReadFlag`
+        // https://github.com/ndesh26/evdev-rs/issues/116
+        let read_flag_clone = ReadFlag::from_bits(read_flag.bits()).unwrap();
+        let event = match device.next_event(read_flag_clone) {
+            Ok((ReadStatus::Success, event)) => event,
+            Ok((
+                ReadStatus::Sync,
+                InputEvent {
+                    event_code: EventCode::EV_SYN(EV_SYN::SYN_DROPPED),
+                    ..
+                },
+            )) => {
+                read_flag = ReadFlag::SYNC;
+                continue;
+            }
+            Ok((ReadStatus::Sync, event)) => event,
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                read_flag = ReadFlag::NORMAL;
+                loop {
+                    match epoll.wait(event_loop.timeout()) {
+                        Ok(()) => {
+                            for gesture in event_loop.check_timers() {
+                                swipe_handler(device_path, gestures, dimensions.as_ref(), gesture);
                             }
+                            continue 'device;
+                        }
+                        Err(e) => {
+                            if e.kind() == ErrorKind::Interrupted {
+                                continue;
+                            }
+                            error!("{device_path}: epoll_wait: {e}");
+                            return Err(e);
                         }
                     }
-                    Err(e) => {
-                        error!("{device_path}: {e}");
-                        break;
-                    }
-                };
-
-                let result = event_loop.add_event(event.time, event.event_code, event.value);
-                if let Some(gesture) = result {
-                    swipe_handler(&gestures, gesture);
                 }
             }
+            Err(e) if e.raw_os_error() == Some(libc::ENODEV) => {
+                info!("{device_path}: Device disconnected");
+                return Ok(());
+            }
+            Err(e) => {
+                error!("{device_path}: {e}");
+                return Err(e);
+            }
+        };
+
+        for gesture in event_loop.add_event(event.time, event.event_code, event.value) {
+            swipe_handler(device_path, gestures, dimensions.as_ref(), gesture);
+        }
+    }
+}
+
+/// The details of a gesture that are passed to the commands it executes, both as `{name}`
+/// placeholders and as `SYNGESTURES_<NAME>` environment variables.
+fn gesture_details(device: &str, event: &GestureEvent) -> Vec<(&'static str, String)> {
+    let gesture = &event.gesture;
+    vec![
+        ("type", gesture.kind().to_owned()),
+        ("fingers", gesture.fingers().count().to_string()),
+        (
+            "direction",
+            gesture.direction().unwrap_or_default().to_owned(),
+        ),
+        ("distance", format!("{:.0}", event.distance)),
+        ("duration", event.duration.as_millis().to_string()),
+        ("velocity", format!("{:.0}", event.velocity())),
+        ("device", device.to_owned()),
+    ]
+}
+
+fn gesture_env<'a>(details: &'a [(&'static str, String)]) -> Vec<(String, &'a str)> {
+    details
+        .iter()
+        .filter(|(_, value)| !value.is_empty())
+        .map(|(name, value)| {
+            let name = format!("SYNGESTURES_{}", name.to_ascii_uppercase());
+            (name, value.as_str())
+        })
+        .collect()
+}
+
+/// Replaces each `{name}` placeholder in `template` with the matching gesture detail. Anything else
+/// between braces is left as-is, so as not to interfere with shell syntax. If `template` is a
+/// `sh -c` command line, the details are quoted so that e.g. a device name can't inject commands.
+fn expand_placeholders(template: &str, details: &[(&'static str, String)], shell: bool) -> String {
+    let mut result = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        result.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let value = rest.find('}').and_then(|end| {
+            let name = &rest[1..end];
+            let (_, value) = details.iter().find(|(n, _)| *n == name)?;
+            Some((value, end))
         });
+        match value {
+            Some((value, end)) if shell => {
+                result.push_str(&shell_quote(value));
+                rest = &rest[end + 1..];
+            }
+            Some((value, end)) => {
+                result.push_str(value);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                result.push('{');
+                rest = &rest[1..];
+            }
+        }
     }
+    result.push_str(rest);
+    result
 }
 
-fn swipe_handler(gestures: &config::GestureMap, gesture: Gesture) {
-    info!("{:?}", gesture);
+/// Quotes `value` as a single shell word.
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+fn swipe_handler(
+    device: &str,
+    gestures: &config::GestureMap,
+    dimensions: Option<&Dimensions>,
+    event: GestureEvent,
+) {
+    info!("{:?}", event);
 
-    let action = match gestures.get(&gesture) {
-        Some(action) => action,
+    if WATCH.load(Ordering::Relaxed) {
+        println!(
+            "{device}: {:?}, distance {:.0}, duration {} ms",
+            event.gesture,
+            event.distance,
+            event.duration.as_millis()
+        );
+        match config::find_binding(gestures, &event, dimensions) {
+            Some((pattern, action)) => println!("    {pattern:?} => {action}"),
+            None => println!("    No matching binding"),
+        }
+        return;
+    }
+
+    let action = match config::find_binding(gestures, &event, dimensions) {
+        Some((_, action)) => action,
         None => return,
     };
 
+    let details = gesture_details(device, &event);
+    if action.sleeps() {
+        // Don't hold up the processing of further events while waiting between actions
+        let action = action.clone();
+        std::thread::spawn(move || run_action(&action, &details));
+    } else {
+        run_action(action, &details);
+    }
+}
+
+fn run_action(action: &Action, details: &[(&'static str, String)]) {
     match action {
         Action::None => {}
         Action::Execute(cmd) => {
             let mut shell = Command::new("sh");
-            shell.args(["-c", cmd]);
+            shell
+                .args(["-c", &expand_placeholders(cmd, details, true)])
+                .envs(gesture_env(details));
             // We have SA_NOCLDWAIT set up, so there's no need to wait for children to prevent
             // zombies.
             if let Err(err) = shell.spawn() {
                 error!("{err}");
-                return;
             };
         }
+        Action::Exec { argv, cwd, env } => {
+            let mut command = Command::new(&argv[0]);
+            command
+                .args(
+                    argv[1..]
+                        .iter()
+                        .map(|arg| expand_placeholders(arg, details, false)),
+                )
+                .envs(gesture_env(details))
+                .envs(env);
+            if let Some(cwd) = cwd {
+                command.current_dir(cwd);
+            }
+            if let Err(err) = command.spawn() {
+                error!("{}: {err}", argv[0]);
+            }
+        }
+        Action::Keys(keys) => {
+            if let Err(err) = uinput::send_keys(keys) {
+                error!("Error sending keys: {err}");
+            }
+        }
+        Action::Click(button) => {
+            if let Err(err) = uinput::click(*button) {
+                error!("Error sending click: {err}");
+            }
+        }
+        Action::Sleep(duration) => std::thread::sleep(*duration),
+        Action::Sequence(actions) => {
+            for action in actions {
+                run_action(action, details);
+            }
+        }
+        Action::SetMode(name) => mode::set(name),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn placeholders() {
+        let details = [
+            ("type", "swipe".to_owned()),
+            ("fingers", "3".to_owned()),
+            ("direction", "up".to_owned()),
+            ("edge", String::new()),
+            ("device", "/dev/input/Bob's touchpad".to_owned()),
+        ];
+        for (template, expected) in [
+            ("", ""),
+            ("echo", "echo"),
+            ("echo {direction}", "echo up"),
+            ("{type}:{fingers}:{direction}", "swipe:3:up"),
+            ("{fingers}{fingers}", "33"),
+            ("echo {edge}.", "echo ."),
+            // Unknown names and shell syntax are left alone
+            ("echo {speed}", "echo {speed}"),
+            ("echo ${HOME}", "echo ${HOME}"),
+            ("f() { echo {direction}; }", "f() { echo up; }"),
+            ("echo {a,b}{direction}", "echo {a,b}up"),
+            ("echo {{direction}}", "echo {up}"),
+            ("echo {direction", "echo {direction"),
+            ("echo }{", "echo }{"),
+            ("{}", "{}"),
+            ("{device}", "/dev/input/Bob's touchpad"),
+        ] {
+            assert_eq!(
+                expand_placeholders(template, &details, false),
+                expected,
+                "{template}"
+            );
+        }
+
+        // Each detail is a single word on a shell command line
+        for (template, expected) in [
+            ("echo", "echo"),
+            ("echo {direction}", "echo 'up'"),
+            ("{type}:{fingers}", "'swipe':'3'"),
+            ("echo {edge}.", "echo ''."),
+            ("echo {speed} ${HOME}", "echo {speed} ${HOME}"),
+            ("echo {device}", r"echo '/dev/input/Bob'\''s touchpad'"),
+        ] {
+            assert_eq!(
+                expand_placeholders(template, &details, true),
+                expected,
+                "{template}"
+            );
+        }
     }
 }
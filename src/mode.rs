@@ -0,0 +1,161 @@
+//! Tracks the active mode, i.e. which set of gesture bindings is in effect. The mode can be changed
+//! by a `set_mode` action or from outside the daemon with `syngestures --mode NAME`, which records
+//! the requested mode in a runtime file and signals all running instances with `SIGUSR1`.
+
+#[allow(unused)]
+use log::{debug, error, info, trace, warn};
+use std::io;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+pub(crate) const DEFAULT_MODE: &str = "default";
+
+/// The active mode, or `None` for the default mode.
+static CURRENT: Mutex<Option<String>> = Mutex::new(None);
+/// Incremented every time the mode changes, so device threads can tell when to update.
+static GENERATION: AtomicUsize = AtomicUsize::new(0);
+/// Set by our `SIGUSR1` handler when the runtime file should be read.
+static SIGUSR1: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_sigusr1(_: libc::c_int) {
+    SIGUSR1.store(true, Ordering::Relaxed);
+}
+
+pub(crate) fn install_signal_handler() {
+    unsafe {
+        let result = libc::signal(libc::SIGUSR1, on_sigusr1 as *const () as libc::sighandler_t);
+        if result == libc::SIG_ERR {
+            warn!("Unable to install SIGUSR1 handler, modes can't be switched externally!");
+        }
+    }
+}
+
+/// The path of the file used to pass the requested mode to running instances.
+fn runtime_file() -> PathBuf {
+    match std::env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) => PathBuf::from(dir).join("syngestures.mode"),
+        None => {
+            let uid = unsafe { libc::getuid() };
+            std::env::temp_dir().join(format!("syngestures-{uid}.mode"))
+        }
+    }
+}
+
+pub(crate) fn current() -> String {
+    let current = CURRENT.lock().unwrap();
+    current.as_deref().unwrap_or(DEFAULT_MODE).to_owned()
+}
+
+pub(crate) fn set(mode: &str) {
+    let mut current = CURRENT.lock().unwrap();
+    if current.as_deref().unwrap_or(DEFAULT_MODE) == mode {
+        return;
+    }
+    info!("Switching to mode {mode}");
+    *current = Some(mode.to_owned());
+    GENERATION.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Returns a number that changes whenever the mode does, first picking up any mode requested
+/// externally since the last call.
+pub(crate) fn generation() -> usize {
+    if SIGUSR1.swap(false, Ordering::Relaxed) {
+        let path = runtime_file();
+        match std::fs::read_to_string(&path) {
+            Ok(mode) => set(mode.trim()),
+            Err(e) => error!("Error reading requested mode from {}: {e}", path.display()),
+        }
+    }
+    GENERATION.load(Ordering::Relaxed)
+}
+
+/// Whether the process with the given `/proc/<pid>/status` belongs to the user `uid` and has
+/// installed our `SIGUSR1` handler. Signalling any other instance would either have it pick up the
+/// mode from a different runtime file or, if it's still starting up (or isn't a daemon at all, e.g.
+/// `--list-devices`), kill it.
+fn accepts_mode_requests(status: &str, uid: libc::uid_t) -> bool {
+    let field = |name: &str| {
+        status
+            .lines()
+            .find_map(|line| line.strip_prefix(name)?.strip_prefix(':'))
+            .map(str::trim)
+    };
+    let real_uid = field("Uid")
+        .and_then(|uids| uids.split_whitespace().next())
+        .and_then(|uid| uid.parse::<libc::uid_t>().ok());
+    let caught_signals = field("SigCgt").and_then(|mask| u64::from_str_radix(mask, 16).ok());
+    match (real_uid, caught_signals) {
+        (Some(real_uid), Some(caught_signals)) => {
+            real_uid == uid && caught_signals & (1 << (libc::SIGUSR1 - 1)) != 0
+        }
+        _ => false,
+    }
+}
+
+/// Asks all running syngestures instances of the current user to switch to `mode`, returning the
+/// number of instances signalled.
+pub(crate) fn request(mode: &str) -> io::Result<usize> {
+    std::fs::write(runtime_file(), mode)?;
+
+    let own_pid = std::process::id();
+    let own_uid = unsafe { libc::getuid() };
+    let mut signalled = 0;
+    for entry in std::fs::read_dir("/proc")? {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(_) => continue,
+        };
+        let pid = match entry
+            .file_name()
+            .to_str()
+            .and_then(|s| s.parse::<u32>().ok())
+        {
+            Some(pid) if pid != own_pid => pid,
+            _ => continue,
+        };
+        match std::fs::read_to_string(entry.path().join("comm")) {
+            Ok(comm) if comm.trim_end() == "syngestures" => {}
+            _ => continue,
+        }
+        match std::fs::read_to_string(entry.path().join("status")) {
+            Ok(status) if accepts_mode_requests(&status, own_uid) => {}
+            _ => continue,
+        }
+        if unsafe { libc::kill(pid as libc::pid_t, libc::SIGUSR1) } == 0 {
+            signalled += 1;
+        } else {
+            let err = io::Error::last_os_error();
+            warn!("Unable to signal syngestures instance {pid}: {err}");
+        }
+    }
+
+    Ok(signalled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mode_request_recipients() {
+        let usr1 = 1u64 << (libc::SIGUSR1 - 1);
+        let status = |uid: u32, caught: u64| {
+            format!(
+                "Name:\tsyngestures\nUid:\t{uid}\t{uid}\t{uid}\t{uid}\nSigCgt:\t{caught:016x}\n"
+            )
+        };
+        for (status, expected) in [
+            (status(1000, usr1), true),
+            (status(1000, usr1 | 1 << (libc::SIGHUP - 1)), true),
+            // Belongs to another user
+            (status(1001, usr1), false),
+            // Hasn't installed the SIGUSR1 handler (yet)
+            (status(1000, 1 << (libc::SIGHUP - 1)), false),
+            (status(1000, 0), false),
+            ("Name:\tsyngestures\n".to_owned(), false),
+        ] {
+            assert_eq!(accepts_mode_requests(&status, 1000), expected, "{status}");
+        }
+    }
+}
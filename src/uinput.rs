@@ -0,0 +1,207 @@
+//! A virtual keyboard and mouse, created through uinput, that lets us emit key presses and mouse
+//! clicks ourselves instead of shelling out to X11-only tools such as `xdotool`.
+
+use evdev_rs::enums::{EventCode, EventType, EV_KEY, EV_REL, EV_SYN};
+use evdev_rs::{DeviceWrapper, InputEvent, TimeVal, UInputDevice, UninitDevice};
+#[allow(unused)]
+use log::{debug, error, info, trace, warn};
+use serde::{Deserialize, Deserializer};
+use std::fmt;
+use std::io;
+use std::str::FromStr;
+use std::sync::Mutex;
+
+const DEVICE_NAME: &str = "syngestures virtual input";
+
+/// The virtual device, shared by all device watcher threads. The lock also keeps the events of
+/// one key combination from being interleaved with those of another.
+static DEVICE: Mutex<Option<UInputDevice>> = Mutex::new(None);
+
+/// A combination of keys to be pressed together, e.g. `ctrl+shift+t`.
+#[derive(Debug, Clone)]
+pub(crate) struct KeyCombo(Vec<EV_KEY>);
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum MouseButton {
+    Left,
+    Right,
+    Middle,
+    Back,
+    Forward,
+}
+
+impl MouseButton {
+    const ALL: [MouseButton; 5] = [
+        MouseButton::Left,
+        MouseButton::Right,
+        MouseButton::Middle,
+        MouseButton::Back,
+        MouseButton::Forward,
+    ];
+
+    fn key(self) -> EV_KEY {
+        match self {
+            MouseButton::Left => EV_KEY::BTN_LEFT,
+            MouseButton::Right => EV_KEY::BTN_RIGHT,
+            MouseButton::Middle => EV_KEY::BTN_MIDDLE,
+            MouseButton::Back => EV_KEY::BTN_SIDE,
+            MouseButton::Forward => EV_KEY::BTN_EXTRA,
+        }
+    }
+}
+
+/// Map a single key name to its key code. Names are case-insensitive and may be given either as
+/// the kernel name with or without the `KEY_` prefix (`KEY_VOLUMEUP`, `volumeup`) or as one of the
+/// common X11 keysym names (`Super_L`, `Return`, `Prior`) used by `xdotool`.
+fn parse_key(name: &str) -> Option<EV_KEY> {
+    let name = name.to_ascii_uppercase();
+    let alias = match name.as_str() {
+        "CTRL" | "CTRL_L" | "CONTROL" | "CONTROL_L" => Some("KEY_LEFTCTRL"),
+        "CTRL_R" | "CONTROL_R" => Some("KEY_RIGHTCTRL"),
+        "ALT" | "ALT_L" => Some("KEY_LEFTALT"),
+        "ALT_R" | "ALTGR" => Some("KEY_RIGHTALT"),
+        "SHIFT" | "SHIFT_L" => Some("KEY_LEFTSHIFT"),
+        "SHIFT_R" => Some("KEY_RIGHTSHIFT"),
+        "SUPER" | "SUPER_L" | "META" | "META_L" | "WIN" | "LOGO" => Some("KEY_LEFTMETA"),
+        "SUPER_R" | "META_R" => Some("KEY_RIGHTMETA"),
+        "RETURN" => Some("KEY_ENTER"),
+        "ESCAPE" => Some("KEY_ESC"),
+        "PRIOR" | "PAGE_UP" => Some("KEY_PAGEUP"),
+        "NEXT" | "PAGE_DOWN" => Some("KEY_PAGEDOWN"),
+        "PERIOD" => Some("KEY_DOT"),
+        "PRINT" => Some("KEY_SYSRQ"),
+        "XF86AUDIORAISEVOLUME" => Some("KEY_VOLUMEUP"),
+        "XF86AUDIOLOWERVOLUME" => Some("KEY_VOLUMEDOWN"),
+        "XF86AUDIOMUTE" => Some("KEY_MUTE"),
+        _ => None,
+    };
+
+    let name = match alias {
+        Some(alias) => alias.to_owned(),
+        None if name.starts_with("KEY_") => name,
+        None => format!("KEY_{name}"),
+    };
+    EV_KEY::from_str(&name).ok()
+}
+
+impl FromStr for KeyCombo {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let keys = s
+            .split('+')
+            .map(|name| {
+                let name = name.trim();
+                parse_key(name).ok_or_else(|| format!("Unknown key name \"{name}\" in \"{s}\""))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if keys.is_empty() {
+            return Err("No keys specified".to_owned());
+        }
+        Ok(KeyCombo(keys))
+    }
+}
+
+impl fmt::Display for KeyCombo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let keys: Vec<String> = self.0.iter().map(|key| format!("{key:?}")).collect();
+        write!(f, "{}", keys.join("+"))
+    }
+}
+
+impl<'de> Deserialize<'de> for KeyCombo {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Create the virtual input device if it doesn't already exist. This should be done well ahead of
+/// its first use, as the display server takes a moment to pick up a newly created device.
+pub(crate) fn init() {
+    let mut device = DEVICE.lock().unwrap();
+    if device.is_some() {
+        return;
+    }
+
+    match create_device() {
+        Ok(uinput) => {
+            debug!("Created uinput device {:?}", uinput.devnode());
+            *device = Some(uinput);
+        }
+        Err(e) => error!("Unable to create uinput device: {e}"),
+    }
+}
+
+fn create_device() -> io::Result<UInputDevice> {
+    let device =
+        UninitDevice::new().ok_or_else(|| io::Error::other("Unable to allocate evdev device"))?;
+    device.set_name(DEVICE_NAME);
+
+    // Only enable the keyboard keys and mouse buttons we may actually press, so the device isn't
+    // mistaken for a joystick, gamepad, or touch/stylus tool.
+    device.enable(EventType::EV_KEY)?;
+    for code in EventCode::EV_KEY(EV_KEY::KEY_ESC).iter() {
+        if matches!(code, EventCode::EV_KEY(key) if format!("{key:?}").starts_with("KEY_")) {
+            device.enable(code)?;
+        }
+    }
+    for button in MouseButton::ALL {
+        device.enable(EventCode::EV_KEY(button.key()))?;
+    }
+
+    // Relative axes are needed for the device to be recognized as a pointer, so clicks are
+    // delivered like those of any other mouse.
+    device.enable(EventType::EV_REL)?;
+    device.enable(EventCode::EV_REL(EV_REL::REL_X))?;
+    device.enable(EventCode::EV_REL(EV_REL::REL_Y))?;
+
+    UInputDevice::create_from_device(&device)
+}
+
+fn emit(device: &UInputDevice, key: EV_KEY, value: i32) -> io::Result<()> {
+    let time = TimeVal::new(0, 0);
+    device.write_event(&InputEvent::new(&time, &EventCode::EV_KEY(key), value))?;
+    device.write_event(&InputEvent::new(
+        &time,
+        &EventCode::EV_SYN(EV_SYN::SYN_REPORT),
+        0,
+    ))
+}
+
+/// Press all the keys in `keys` in order, then release them in the reverse order. If pressing a
+/// key fails, the keys pressed so far are still released so no modifiers are left stuck, and the
+/// first error is returned.
+fn press(keys: &[EV_KEY]) -> io::Result<()> {
+    let device = DEVICE.lock().unwrap();
+    let device = device
+        .as_ref()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "uinput device not available"))?;
+
+    let mut result = Ok(());
+    let mut pressed = 0;
+    for key in keys {
+        // The key is released even if pressing it failed, in case only its SYN_REPORT was lost
+        pressed += 1;
+        result = emit(device, *key, 1);
+        if result.is_err() {
+            break;
+        }
+    }
+    for key in keys[..pressed].iter().rev() {
+        result = result.and(emit(device, *key, 0));
+    }
+    result
+}
+
+pub(crate) fn send_keys(keys: &KeyCombo) -> io::Result<()> {
+    press(&keys.0)
+}
+
+pub(crate) fn click(button: MouseButton) -> io::Result<()> {
+    press(&[button.key()])
+}